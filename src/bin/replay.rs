@@ -0,0 +1,46 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use minimal_repro_lightyear_rollbacks::{
+    lightyear::replay::{recording::Recording, replay},
+    FIXED_TIMESTEP_HZ,
+};
+
+/// Simulates a recording made with F5 in the game again, offline and headless.
+#[derive(Parser)]
+struct Args {
+    /// The `.ron` file from the `recordings` directory
+    recording: PathBuf,
+}
+
+fn main() {
+    let args = Args::parse();
+    let recording = match Recording::load(&args.recording) {
+        Ok(recording) => recording,
+        Err(err) => {
+            eprintln!("Could not load {:?}: {}", args.recording, err);
+            std::process::exit(1);
+        }
+    };
+    if recording.fixed_timestep_hz != FIXED_TIMESTEP_HZ {
+        eprintln!(
+            "Recorded at {} Hz but replaying at {} Hz, expect a divergence",
+            recording.fixed_timestep_hz, FIXED_TIMESTEP_HZ
+        );
+    }
+
+    match replay(&recording) {
+        Err(err) => {
+            eprintln!("Could not replay {:?}: {}", args.recording, err);
+            std::process::exit(1);
+        }
+        Ok(Some(divergence)) => {
+            println!(
+                "Diverged at tick {:?}: recorded {} but replayed {}",
+                divergence.tick, divergence.recorded, divergence.replayed
+            );
+            std::process::exit(1);
+        }
+        Ok(None) => println!("Replayed {} ticks without diverging", recording.ticks.len()),
+    }
+}
@@ -0,0 +1,61 @@
+use std::time::Duration;
+
+use bevy::{app::ScheduleRunnerPlugin, log::LogPlugin, prelude::*, state::app::StatesPlugin};
+use lightyear::prelude::server::ServerCommands;
+use minimal_repro_lightyear_rollbacks::{
+    lightyear::{settings::NetSettings, MyDedicatedServerPlugin},
+    map::{MapLoadFailed, MyMapPlugin},
+    my_states::MyStatesPlugin,
+    MyPhysicsPlugin, FIXED_TIMESTEP_HZ,
+};
+
+// Headless dedicated server: no window, no renderer, only the server side of the game.
+fn main() {
+    let settings = NetSettings::from_args();
+    if let Err(err) = settings.check_server() {
+        eprintln!("Can't start the server: {}", err);
+        std::process::exit(1);
+    }
+
+    let mut app = App::new();
+
+    app.insert_resource(settings)
+        .add_plugins((
+            MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(
+                1.0 / FIXED_TIMESTEP_HZ,
+            ))),
+            LogPlugin::default(),
+            StatesPlugin,
+            TransformPlugin,
+            HierarchyPlugin,
+            // avian looks up mesh assets for its collider constructors
+            AssetPlugin::default(),
+            MyPhysicsPlugin,
+            MyDedicatedServerPlugin,
+            MyStatesPlugin,
+            MyMapPlugin,
+        ))
+        .init_asset::<Mesh>()
+        .add_systems(Startup, start_server)
+        .add_systems(Update, exit_on_map_load_failure);
+
+    app.run();
+}
+
+fn start_server(mut commands: Commands) {
+    commands.start_server();
+}
+
+/// `--map` names an existing map, but it can still fail to parse.
+fn exit_on_map_load_failure(
+    mut events: EventReader<MapLoadFailed>,
+    mut app_exit: EventWriter<AppExit>,
+) {
+    for MapLoadFailed { name, error } in events.read() {
+        eprintln!(
+            "Can't start the server: could not load map {:?}: {}",
+            name, error
+        );
+        app_exit.send(AppExit::error());
+    }
+}
@@ -0,0 +1,33 @@
+use avian3d::{
+    prelude::*,
+    sync::{SyncConfig, SyncPlugin},
+    PhysicsPlugins,
+};
+use bevy::prelude::*;
+
+pub const FIXED_TIMESTEP_HZ: f64 = 64.0;
+
+pub mod lightyear;
+pub mod map;
+pub mod my_states;
+pub mod my_ui;
+
+/// Avian physics configured the same way for the host-client app and the dedicated server.
+pub struct MyPhysicsPlugin;
+
+impl Plugin for MyPhysicsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((
+            PhysicsPlugins::new(FixedUpdate)
+                .build()
+                .disable::<SyncPlugin>()
+                .disable::<SleepingPlugin>(),
+            SyncPlugin::new(PostUpdate),
+        ))
+        .insert_resource(SyncConfig {
+            transform_to_position: false,
+            position_to_transform: true,
+        })
+        .insert_resource(Time::new_with(Physics::fixed_once_hz(FIXED_TIMESTEP_HZ)));
+    }
+}
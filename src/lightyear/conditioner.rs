@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use clap::ValueEnum;
+use lightyear::prelude::LinkConditionerConfig;
+use serde::{Deserialize, Serialize};
+
+/// Network conditions that are handy when reproducing rollbacks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ValueEnum)]
+pub enum ConditionerPreset {
+    #[default]
+    Off,
+    Lan,
+    CrossContinent,
+    BadWifi,
+}
+
+impl ConditionerPreset {
+    pub const ALL: [ConditionerPreset; 4] = [
+        ConditionerPreset::Off,
+        ConditionerPreset::Lan,
+        ConditionerPreset::CrossContinent,
+        ConditionerPreset::BadWifi,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ConditionerPreset::Off => "Off",
+            ConditionerPreset::Lan => "LAN",
+            ConditionerPreset::CrossContinent => "Cross-continent",
+            ConditionerPreset::BadWifi => "Bad Wi-Fi",
+        }
+    }
+
+    pub fn values(&self) -> ConditionerValues {
+        let (latency_ms, jitter_ms, loss) = match self {
+            ConditionerPreset::Off => (0, 0, 0.0),
+            ConditionerPreset::Lan => (2, 1, 0.0),
+            ConditionerPreset::CrossContinent => (150, 20, 0.01),
+            ConditionerPreset::BadWifi => (60, 40, 0.05),
+        };
+        ConditionerValues {
+            latency_ms,
+            jitter_ms,
+            loss,
+        }
+    }
+}
+
+/// The incoming latency, jitter and packet loss a link conditioner simulates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConditionerValues {
+    pub latency_ms: u64,
+    pub jitter_ms: u64,
+    /// Between 0 and 1.
+    pub loss: f32,
+}
+
+impl ConditionerValues {
+    pub fn to_config(&self) -> LinkConditionerConfig {
+        LinkConditionerConfig {
+            incoming_latency: Duration::from_millis(self.latency_ms),
+            incoming_jitter: Duration::from_millis(self.jitter_ms),
+            incoming_loss: self.loss,
+        }
+    }
+}
+
+/// The conditioners used the next time we join (client) or host (server).
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct LinkConditioners {
+    pub client: ConditionerValues,
+    pub server: ConditionerValues,
+}
+
+impl LinkConditioners {
+    pub fn from_preset(preset: ConditionerPreset) -> Self {
+        Self {
+            client: preset.values(),
+            server: preset.values(),
+        }
+    }
+}
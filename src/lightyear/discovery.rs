@@ -0,0 +1,53 @@
+use std::{net::SocketAddr, time::Duration};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// UDP port servers broadcast their beacon to and clients in the main menu listen on.
+pub const DISCOVERY_PORT: u16 = 4002;
+
+/// How often a running server announces itself.
+pub const BEACON_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Servers we have not heard from for this long are dropped from the list.
+pub const BEACON_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Lets listeners tell our beacons apart from anything else broadcast on the port.
+const BEACON_MAGIC: &[u8] = b"repro-beacon:";
+
+/// What a server broadcasts on the LAN so clients can list it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ServerBeacon {
+    pub name: String,
+    pub map: String,
+    pub players: u16,
+    /// The port to join on, at the address the beacon came from.
+    pub port: u16,
+}
+
+impl ServerBeacon {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = BEACON_MAGIC.to_vec();
+        bytes.extend(ron::to_string(self).unwrap_or_default().into_bytes());
+        bytes
+    }
+
+    /// `None` for anything that is not one of our beacons.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let contents = std::str::from_utf8(bytes.strip_prefix(BEACON_MAGIC)?).ok()?;
+        ron::from_str(contents).ok()
+    }
+}
+
+/// A server found on the LAN.
+#[derive(Debug, Clone)]
+pub struct DiscoveredServer {
+    /// The address to join, made of the beacon's sender and its advertised port.
+    pub addr: SocketAddr,
+    pub beacon: ServerBeacon,
+    pub last_seen: Duration,
+}
+
+/// The servers currently announcing themselves on the LAN, filled while in the main menu.
+#[derive(Resource, Default, Debug)]
+pub struct DiscoveredServers(pub Vec<DiscoveredServer>);
@@ -1,73 +1,272 @@
 use std::{
-    net::{IpAddr, Ipv4Addr, SocketAddr},
+    io::{Read, Write},
+    net::{IpAddr, Ipv4Addr, SocketAddr, TcpStream},
     time::Duration,
 };
 
-use bevy::{ecs::system::SystemParam, prelude::*};
-use lightyear::prelude::{
-    client::{self, Authentication},
-    server, CompressionConfig, Key, LinkConditionerConfig,
+use bevy::{
+    ecs::system::SystemParam,
+    prelude::*,
+    tasks::{block_on, futures_lite::future, IoTaskPool, Task},
 };
+use lightyear::{
+    connection::netcode::{ConnectToken, CONNECT_TOKEN_BYTES},
+    prelude::{
+        client::{self, Authentication},
+        server, CompressionConfig, Mode,
+    },
+};
+
+use crate::map::SelectedMap;
 
-use super::my_shared::shared_config;
+use super::{
+    conditioner::LinkConditioners,
+    my_shared::shared_config,
+    settings::{AuthMode, NetSettings},
+};
 
 pub const CLIENT_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0);
-pub const SERVER_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), NETCODE_PORT);
 pub const NETCODE_PORT: u16 = 4000;
+pub const AUTH_PORT: u16 = 4001;
+
+/// How long talking to the server's auth service may take before joining fails.
+const AUTH_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Why the last attempt to host or join failed, shown in the main menu.
+#[derive(Resource, Default, Debug)]
+pub struct ConnectionError(pub Option<String>);
+
+/// The client disconnected to rejoin the same server with a new conditioner,
+/// so the disconnect does not end the game.
+#[derive(Resource)]
+pub(crate) struct PendingReconnect;
+
+/// The server the client last set out to join.
+#[derive(Resource, Default, Debug)]
+pub struct JoinedServer(pub Option<SocketAddr>);
+
+/// The auth service of the server we join being asked in the background how to authenticate.
+#[derive(Resource, Default)]
+pub struct PendingAuthentication(Option<Task<Result<Authentication, String>>>);
+
+impl PendingAuthentication {
+    pub fn is_pending(&self) -> bool {
+        self.0.is_some()
+    }
+}
 
 #[derive(SystemParam)]
 pub struct MyNetConfigControl<'w> {
-    _server_config: ResMut<'w, server::ServerConfig>,
+    server_config: ResMut<'w, server::ServerConfig>,
     client_config: ResMut<'w, client::ClientConfig>,
+    settings: Res<'w, NetSettings>,
+    connection_error: ResMut<'w, ConnectionError>,
+    conditioners: Res<'w, LinkConditioners>,
+    selected_map: ResMut<'w, SelectedMap>,
+    pending_auth: ResMut<'w, PendingAuthentication>,
+    joined_server: ResMut<'w, JoinedServer>,
     // steam_client: ResMut<'w, SteamClientResource>,
 }
 
 impl<'w> MyNetConfigControl<'w> {
-    pub(crate) fn set_to_join(&mut self) {
-        let client_config = {
-            println!("Setting client to join");
-            let server_addr = SERVER_ADDR;
-            let client_addr = CLIENT_ADDR;
-            let random_client_id = 1;
-            client::NetConfig::Netcode {
-                auth: Authentication::Manual {
+    /// Starts asking the auth service of the server how to authenticate, in the background:
+    /// [`MyNetConfigControl::poll_authentication`] says when joining can go on.
+    ///
+    /// With token authentication it hands out the connect token, with manual authentication
+    /// it tells whether our id is already in use. Netcode ignores connection requests for an id
+    /// in use, so that is the only place a client can find out.
+    pub(crate) fn set_to_join(&mut self, server_addr: SocketAddr) {
+        self.connection_error.0 = None;
+        // the server tells us which map to build once we are connected
+        self.selected_map.0 = None;
+        self.authenticate(server_addr);
+    }
+
+    /// Joins the server we last joined again, keeping its map: the connection is rebuilt with
+    /// the current conditioner, which lightyear can't swap on a live one.
+    pub(crate) fn set_to_rejoin(&mut self) {
+        match self.joined_server.0 {
+            Some(server_addr) => self.authenticate(server_addr),
+            None => self.connection_error.0 = Some("No server to rejoin".to_string()),
+        }
+    }
+
+    pub(crate) fn is_authenticating(&self) -> bool {
+        self.pending_auth.is_pending()
+    }
+
+    fn authenticate(&mut self, server_addr: SocketAddr) {
+        self.joined_server.0 = Some(server_addr);
+        // the auth service runs next to the server we join
+        let auth_addr = SocketAddr::new(server_addr.ip(), self.settings.auth_port);
+        let task = match self.settings.auth {
+            AuthMode::Manual => {
+                let client_id = self.settings.client_id.resolve();
+                println!(
+                    "Setting client to join {} with {} id {}",
+                    server_addr, self.settings.client_id, client_id
+                );
+                let auth = Authentication::Manual {
                     server_addr,
-                    client_id: random_client_id,
-                    private_key: Key::default(),
-                    protocol_id: 0,
-                },
-                config: client::NetcodeConfig::default(),
-                io: client::IoConfig {
-                    transport: client::ClientTransport::UdpSocket(client_addr),
-                    conditioner: Some(MyNetConfigControl::get_default_conditioner()),
-                    compression: CompressionConfig::None,
-                },
+                    client_id,
+                    private_key: self.settings.client_key(),
+                    protocol_id: self.settings.protocol_id,
+                };
+                IoTaskPool::get()
+                    .spawn(async move { check_client_id(auth_addr, client_id).map(|_| auth) })
+            }
+            AuthMode::Token => {
+                println!(
+                    "Setting client to join {} with a token from {}",
+                    server_addr, auth_addr
+                );
+                IoTaskPool::get().spawn(async move {
+                    fetch_connect_token(auth_addr)
+                        .map(Authentication::Token)
+                        .map_err(|err| {
+                            format!("Could not get a connect token from {}: {}", auth_addr, err)
+                        })
+                })
             }
         };
+        self.pending_auth.0 = Some(task);
+    }
+
+    /// Returns `true` once the auth service answered and the client can connect,
+    /// if it refused the reason is in [`ConnectionError`].
+    pub(crate) fn poll_authentication(&mut self) -> bool {
+        let Some(task) = self.pending_auth.0.as_mut() else {
+            return false;
+        };
+        let Some(result) = block_on(future::poll_once(task)) else {
+            return false;
+        };
+        self.pending_auth.0 = None;
 
+        match result {
+            Ok(auth) => {
+                self.set_client_auth(auth);
+                true
+            }
+            Err(err) => {
+                self.connection_error.0 = Some(err);
+                false
+            }
+        }
+    }
+
+    fn set_client_auth(&mut self, auth: Authentication) {
         *self.client_config = client::ClientConfig {
-            shared: shared_config(),
-            net: client_config,
+            shared: shared_config(Mode::Separate),
+            net: client::NetConfig::Netcode {
+                auth,
+                config: client::NetcodeConfig::default(),
+                io: client::IoConfig {
+                    transport: client::ClientTransport::UdpSocket(self.settings.client_addr),
+                    conditioner: Some(self.conditioners.client.to_config()),
+                    compression: CompressionConfig::None,
+                },
+            },
             ..default()
         };
     }
 
-    pub(crate) fn set_to_host(&mut self) {
+    /// Joins the address typed in the main menu, reporting it in [`ConnectionError`] if it is not one.
+    pub(crate) fn set_to_join_address(&mut self, address: &str) {
+        match address.trim().parse() {
+            Ok(server_addr) => self.set_to_join(server_addr),
+            Err(_) => {
+                self.connection_error.0 = Some(format!(
+                    "{:?} is no server address, expected something like 127.0.0.1:{}",
+                    address, NETCODE_PORT
+                ));
+            }
+        }
+    }
+
+    /// Returns `false` if the settings can't run a server, the reason is then in [`ConnectionError`].
+    pub(crate) fn set_to_host(&mut self) -> bool {
         println!("Setting client to host");
         let net_config = client::NetConfig::Local { id: 0 };
 
+        self.connection_error.0 = None;
+        match self
+            .settings
+            .check_server()
+            .and_then(|_| server_net_config(&self.settings, &self.conditioners))
+        {
+            Ok(server_net_config) => self.server_config.net = vec![server_net_config],
+            Err(err) => {
+                self.connection_error.0 = Some(format!("Could not host: {}", err));
+                return false;
+            }
+        }
         *self.client_config = client::ClientConfig {
-            shared: shared_config(),
+            shared: shared_config(Mode::HostServer),
             net: net_config,
             ..default()
         };
+        true
     }
+}
+
+/// The netcode transport the server listens with, if the settings can run a server.
+pub(crate) fn server_net_config(
+    settings: &NetSettings,
+    conditioners: &LinkConditioners,
+) -> Result<server::NetConfig, String> {
+    Ok(server::NetConfig::Netcode {
+        io: server::IoConfig {
+            transport: server::ServerTransport::UdpSocket(settings.bind_addr()),
+            conditioner: Some(conditioners.server.to_config()),
+            ..default()
+        },
+        config: server::NetcodeConfig::default()
+            .with_protocol_id(settings.protocol_id)
+            .with_key(settings.server_key()?),
+    })
+}
+
+/// Fetches a connect token from the server's auth service, blocking, so it runs on the [`IoTaskPool`].
+fn fetch_connect_token(auth_addr: SocketAddr) -> Result<ConnectToken, String> {
+    let mut stream =
+        TcpStream::connect_timeout(&auth_addr, AUTH_TIMEOUT).map_err(|err| err.to_string())?;
+    stream
+        .set_read_timeout(Some(AUTH_TIMEOUT))
+        .map_err(|err| err.to_string())?;
+
+    let mut buffer = [0u8; CONNECT_TOKEN_BYTES];
+    stream
+        .read_exact(&mut buffer)
+        .map_err(|err| err.to_string())?;
+    ConnectToken::try_from_bytes(&buffer).map_err(|err| format!("{:?}", err))
+}
 
-    pub(crate) fn get_default_conditioner() -> LinkConditionerConfig {
-        LinkConditionerConfig {
-            incoming_latency: Duration::from_millis(0),
-            incoming_jitter: Duration::from_millis(0),
-            incoming_loss: 0.0,
+/// Asks the server's auth service whether `client_id` is free, blocking, so it runs on the [`IoTaskPool`].
+///
+/// Only errs if the id is in use: a server whose auth service we can't reach may still
+/// let us in, if not the connection times out.
+fn check_client_id(auth_addr: SocketAddr, client_id: u64) -> Result<(), String> {
+    let answer = TcpStream::connect_timeout(&auth_addr, AUTH_TIMEOUT).and_then(|mut stream| {
+        stream.set_read_timeout(Some(AUTH_TIMEOUT))?;
+        stream.set_write_timeout(Some(AUTH_TIMEOUT))?;
+        stream.write_all(&client_id.to_le_bytes())?;
+        let mut id_free = [0u8; 1];
+        stream.read_exact(&mut id_free)?;
+        Ok(id_free[0] != 0)
+    });
+    match answer {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(format!(
+            "The server already has a client with id {}, join with another one",
+            client_id
+        )),
+        Err(err) => {
+            warn!(
+                "Could not check with {} whether id {} is free, joining anyway: {}",
+                auth_addr, client_id, err
+            );
+            Ok(())
         }
     }
 }
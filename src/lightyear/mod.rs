@@ -1,18 +1,51 @@
 use bevy::prelude::*;
+use conditioner::LinkConditioners;
+use lightyear::prelude::Mode;
+use settings::NetSettings;
 
+pub mod conditioner;
+pub mod discovery;
+pub mod lib;
 mod my_client;
 mod my_server;
-mod my_shared;
-pub mod lib;
+pub mod my_shared;
+pub mod net_stats;
+pub mod replay;
+pub mod settings;
 
 pub struct MyLightyearPlugin;
 
 impl Plugin for MyLightyearPlugin {
     fn build(&self, app: &mut App) {
+        init_net_resources(app);
         app.add_plugins((
             my_client::MyClientPlugin,
-            my_server::MyServerPlugin,
+            my_server::MyServerPlugin {
+                mode: Mode::HostServer,
+            },
+            my_shared::MySharedPlugin,
+        ));
+    }
+}
+
+/// Only the server side of the game, for running a headless dedicated server
+/// that `Mode::Separate` clients join.
+pub struct MyDedicatedServerPlugin;
+
+impl Plugin for MyDedicatedServerPlugin {
+    fn build(&self, app: &mut App) {
+        init_net_resources(app);
+        app.add_plugins((
+            my_server::MyServerPlugin {
+                mode: Mode::Separate,
+            },
             my_shared::MySharedPlugin,
         ));
     }
 }
+
+fn init_net_resources(app: &mut App) {
+    app.init_resource::<NetSettings>();
+    let preset = app.world().resource::<NetSettings>().conditioner;
+    app.insert_resource(LinkConditioners::from_preset(preset));
+}
@@ -0,0 +1,133 @@
+use bevy::{
+    input::{
+        keyboard::{Key, KeyboardInput},
+        ButtonState, InputSystem,
+    },
+    prelude::*,
+};
+use leafwing_input_manager::{
+    plugin::InputManagerSystem,
+    prelude::{ActionState, InputMap},
+};
+use lightyear::prelude::{client::MessageEvent, ClientConnectionManager};
+
+use crate::{
+    lightyear::my_shared::{
+        chat::{ChatBroadcast, ChatInput, ChatLine, ChatLog, ChatMessage, MAX_CHAT_LENGTH},
+        lib::{Channel1, PlayerActions},
+    },
+    my_states::{InGame, InGameUnpaused},
+};
+
+/// Receives the chat and lets the player type into it, without moving while typing.
+pub struct MyClientChatPlugin;
+
+impl Plugin for MyClientChatPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChatLog>()
+            .init_resource::<ChatInput>()
+            .add_systems(
+                PreUpdate,
+                (
+                    type_chat_message
+                        .after(InputSystem)
+                        .before(InputManagerSystem::Update)
+                        .run_if(in_state(InGame)),
+                    suppress_player_input.in_set(InputManagerSystem::ManualControl),
+                ),
+            )
+            .add_systems(Update, receive_chat_messages.run_if(in_state(InGame)))
+            .add_systems(OnExit(InGame), clear_chat);
+    }
+}
+
+/// Enter opens the chat box and sends what was typed, Escape throws it away.
+///
+/// While the box is open the keyboard belongs to it: the pressed keys are cleared
+/// before leafwing and the rest of the game get to see them.
+fn type_chat_message(
+    mut events: EventReader<KeyboardInput>,
+    mut keys: ResMut<ButtonInput<KeyCode>>,
+    mut chat_input: ResMut<ChatInput>,
+    unpaused: Option<Res<State<InGameUnpaused>>>,
+    mut connection_manager: ResMut<ClientConnectionManager>,
+) {
+    let was_open = chat_input.open;
+    for event in events.read() {
+        if event.state != ButtonState::Pressed {
+            continue;
+        }
+        if !chat_input.open {
+            if event.logical_key == Key::Enter && unpaused.is_some() {
+                chat_input.open = true;
+            }
+            continue;
+        }
+
+        match &event.logical_key {
+            Key::Enter => {
+                let text = std::mem::take(&mut chat_input.draft);
+                if !text.trim().is_empty() {
+                    let _ = connection_manager.send_message::<Channel1, _>(&mut ChatMessage(text));
+                }
+                chat_input.open = false;
+            }
+            Key::Escape => {
+                chat_input.draft.clear();
+                chat_input.open = false;
+            }
+            Key::Backspace => {
+                chat_input.draft.pop();
+            }
+            Key::Space => push_chars(&mut chat_input.draft, " "),
+            Key::Character(characters) => push_chars(&mut chat_input.draft, characters),
+            _ => {}
+        }
+    }
+
+    if was_open || chat_input.open {
+        keys.reset_all();
+    }
+}
+
+fn push_chars(draft: &mut String, characters: &str) {
+    for c in characters.chars().filter(|c| !c.is_control()) {
+        if draft.chars().count() >= MAX_CHAT_LENGTH {
+            return;
+        }
+        draft.push(c);
+    }
+}
+
+// The keyboard is already cleared, this also stops the mouse from looking around.
+fn suppress_player_input(
+    chat_input: Res<ChatInput>,
+    mut query: Query<&mut ActionState<PlayerActions>, With<InputMap<PlayerActions>>>,
+) {
+    if !chat_input.open {
+        return;
+    }
+    for mut action_state in &mut query {
+        *action_state = ActionState::default();
+    }
+}
+
+fn receive_chat_messages(
+    mut events: EventReader<MessageEvent<ChatBroadcast>>,
+    mut chat_log: ResMut<ChatLog>,
+    time: Res<Time>,
+) {
+    for event in events.read() {
+        let ChatBroadcast { sender, text } = event.message();
+        chat_log.push(ChatLine {
+            sender: *sender,
+            text: text.clone(),
+            received: time.elapsed(),
+        });
+    }
+}
+
+fn clear_chat(mut chat_log: ResMut<ChatLog>, mut chat_input: ResMut<ChatInput>) {
+    chat_log.0.clear();
+    *chat_input = ChatInput::default();
+}
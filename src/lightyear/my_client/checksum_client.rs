@@ -0,0 +1,134 @@
+use std::collections::VecDeque;
+
+use avian3d::prelude::*;
+use bevy::{prelude::*, utils::HashMap};
+use lightyear::prelude::{
+    client::{MessageEvent, Predicted, Rollback},
+    is_host_server, ClientId, Tick, TickManager,
+};
+
+use crate::{
+    lightyear::my_shared::{
+        checksum::{ChecksumMessage, PlayerChecksum},
+        lib::{FixedSet, PhysicalPlayerBodyMarker, PhysicalPlayerHeadMarker, PlayerId},
+    },
+    my_states::InGame,
+};
+
+/// How many ticks of predicted checksums we keep around until the server's arrive.
+const HISTORY_TICKS: usize = 128;
+
+/// Compares the predicted player state with the server's checksums, tick by tick.
+pub struct MyClientChecksumPlugin;
+
+impl Plugin for MyClientChecksumPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PredictedChecksums>()
+            .init_resource::<Divergences>()
+            .add_systems(OnEnter(InGame), reset_checksums)
+            .add_systems(
+                FixedUpdate,
+                record_predicted_checksums
+                    .after(FixedSet::Physics)
+                    .run_if(not(is_host_server)),
+            )
+            .add_systems(Update, compare_checksums.run_if(not(is_host_server)));
+    }
+}
+
+#[derive(Resource, Default)]
+struct PredictedChecksums(HashMap<ClientId, VecDeque<(Tick, PlayerChecksum)>>);
+
+/// For every player that is out of sync, the first tick at which it diverged.
+#[derive(Resource, Default)]
+struct Divergences(HashMap<ClientId, Tick>);
+
+fn reset_checksums(
+    mut checksums: ResMut<PredictedChecksums>,
+    mut divergences: ResMut<Divergences>,
+) {
+    checksums.0.clear();
+    divergences.0.clear();
+}
+
+fn record_predicted_checksums(
+    tick_manager: Res<TickManager>,
+    rollback: Option<Res<Rollback>>,
+    mut checksums: ResMut<PredictedChecksums>,
+    player_body_query: Query<
+        (
+            &PlayerId,
+            &Position,
+            &Rotation,
+            &LinearVelocity,
+            &PhysicalPlayerBodyMarker,
+            Option<&Children>,
+        ),
+        With<Predicted>,
+    >,
+    player_head_query: Query<&PhysicalPlayerHeadMarker>,
+) {
+    // get the tick, even if during rollback
+    let tick = rollback
+        .as_ref()
+        .map(|rb| tick_manager.tick_or_rollback_tick(rb))
+        .unwrap_or(tick_manager.tick());
+
+    for (player_id, position, rotation, linear_velocity, body, children) in &player_body_query {
+        let head = children
+            .into_iter()
+            .flatten()
+            .find_map(|entity| player_head_query.get(*entity).ok());
+        let checksum = PlayerChecksum::new(position, rotation, linear_velocity, body, head);
+
+        let history = checksums.0.entry(player_id.0).or_default();
+        // a rollback rewrites the ticks it replays
+        while history.back().is_some_and(|(t, _)| *t >= tick) {
+            history.pop_back();
+        }
+        history.push_back((tick, checksum));
+        if history.len() > HISTORY_TICKS {
+            history.pop_front();
+        }
+    }
+}
+
+fn compare_checksums(
+    mut events: EventReader<MessageEvent<ChecksumMessage>>,
+    checksums: Res<PredictedChecksums>,
+    mut divergences: ResMut<Divergences>,
+) {
+    for event in events.read() {
+        let message = event.message();
+        for (client_id, server_checksum) in &message.players {
+            let Some((_, predicted_checksum)) = checksums
+                .0
+                .get(client_id)
+                .and_then(|history| history.iter().find(|(t, _)| *t == message.tick))
+            else {
+                continue;
+            };
+
+            let diverged = predicted_checksum.diff(server_checksum);
+            match (diverged.is_empty(), divergences.0.get(client_id)) {
+                (false, None) => {
+                    warn!(
+                        "Player {:?} first diverged from the server at tick {:?}: {}",
+                        client_id,
+                        message.tick,
+                        diverged.join(", ")
+                    );
+                    divergences.0.insert(*client_id, message.tick);
+                }
+                (true, Some(since)) => {
+                    info!(
+                        "Player {:?} is back in sync at tick {:?} (diverged since tick {:?})",
+                        client_id, message.tick, since
+                    );
+                    divergences.0.remove(client_id);
+                }
+                _ => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use bevy::prelude::*;
+use lightyear::prelude::{
+    client::{
+        ClientCommands, ClientConnection, Confirmed, DisconnectEvent, Interpolated, MessageEvent,
+        NetClient, NetworkingState as ClientNetworkingState, Predicted,
+    },
+    is_host_server,
+    server::{NetworkingState as ServerNetworkingState, ServerCommands},
+    ClientConnectionManager, PrePredicted,
+};
+
+use crate::{
+    lightyear::{
+        lib::{
+            ConnectionError, JoinedServer, MyNetConfigControl, PendingAuthentication,
+            PendingReconnect,
+        },
+        my_shared::{
+            lib::{Channel1, ConnectionRejected, JoinRequest, MapSelection, SessionAccepted},
+            lobby::Lobby,
+        },
+    },
+    map::{MapLoadFailed, SelectedMap},
+    my_states::{GameState, InGame, InGamePaused},
+};
+
+pub struct MyClientConnectionPlugin;
+
+impl Plugin for MyClientConnectionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ConnectionError>()
+            .init_resource::<PendingAuthentication>()
+            .init_resource::<JoinedServer>()
+            .init_resource::<Session>()
+            .add_systems(
+                OnEnter(ClientNetworkingState::Connected),
+                (
+                    request_session.run_if(not(is_host_server)),
+                    start_host_session.run_if(is_host_server),
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    (
+                        handle_rejection,
+                        handle_failed_connection,
+                        handle_disconnection,
+                        (despawn_replicated_entities, rejoin).chain().run_if(
+                            resource_exists::<PendingReconnect>
+                                .and_then(in_state(ClientNetworkingState::Disconnected)),
+                        ),
+                    )
+                        .chain(),
+                    handle_map_selection,
+                    leave_on_map_load_failure,
+                    connect_once_authenticated
+                        .run_if(|pending: Res<PendingAuthentication>| pending.is_pending()),
+                    handle_session_accepted,
+                    leave_game.run_if(in_state(InGamePaused).or_else(in_state(GameState::Lobby))),
+                ),
+            )
+            .add_systems(OnExit(InGame), despawn_replicated_entities)
+            .add_systems(OnEnter(GameState::MainMenu), clear_lobby)
+            .add_systems(OnEnter(ClientNetworkingState::Connecting), clear_left_game);
+    }
+}
+
+/// Joining goes on once the server's auth service let us in.
+fn connect_once_authenticated(
+    mut commands: Commands,
+    mut network: MyNetConfigControl,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if network.poll_authentication() {
+        commands.connect_client();
+    } else if !network.is_authenticating() && *state.get() != GameState::MainMenu {
+        // rejoining failed, the main menu says why
+        next_state.set(GameState::MainMenu);
+    }
+}
+
+/// The link conditioner panel dropped the connection to rebuild it with a new conditioner,
+/// the server gives us our body back through the session.
+fn rejoin(mut commands: Commands, mut network: MyNetConfigControl) {
+    commands.remove_resource::<PendingReconnect>();
+    network.set_to_rejoin();
+}
+
+/// Our session on the server, kept after a disconnect so that we can resume it.
+#[derive(Resource, Default, Debug)]
+pub(super) struct Session {
+    token: Option<u64>,
+    /// The server told us to spawn a new player, which happens once the map is built.
+    pub(super) needs_body: bool,
+}
+
+fn request_session(
+    mut session: ResMut<Session>,
+    mut connection_manager: ResMut<ClientConnectionManager>,
+) {
+    session.needs_body = false;
+    let _ = connection_manager.send_message::<Channel1, _>(&mut JoinRequest {
+        resume_token: session.token,
+    });
+}
+
+/// The host's player lives in the server's world, so there is never anything to resume.
+fn start_host_session(mut session: ResMut<Session>) {
+    session.token = None;
+    session.needs_body = true;
+}
+
+fn handle_session_accepted(
+    mut events: EventReader<MessageEvent<SessionAccepted>>,
+    mut session: ResMut<Session>,
+) {
+    for event in events.read() {
+        let SessionAccepted { token, resumed } = event.message();
+        if *resumed {
+            info!("Resumed our previous session");
+        }
+        session.token = Some(*token);
+        session.needs_body = !resumed;
+    }
+}
+
+/// We left on purpose, so the disconnect that follows is no error.
+#[derive(Resource)]
+struct LeftGame;
+
+fn clear_left_game(mut commands: Commands) {
+    commands.remove_resource::<LeftGame>();
+}
+
+fn handle_rejection(
+    mut events: EventReader<MessageEvent<ConnectionRejected>>,
+    mut connection_error: ResMut<ConnectionError>,
+) {
+    for event in events.read() {
+        let message = match event.message() {
+            ConnectionRejected::TooManyInvalidInputs => {
+                "Kicked by the server for sending too many invalid inputs".to_string()
+            }
+        };
+        error!("{}", message);
+        connection_error.0 = Some(message);
+    }
+}
+
+fn handle_map_selection(
+    mut events: EventReader<MessageEvent<MapSelection>>,
+    mut selected_map: ResMut<SelectedMap>,
+) {
+    for event in events.read() {
+        let MapSelection(map) = event.message();
+        info!("The server plays on map {:?}", map);
+        selected_map.set_if_neq(SelectedMap(Some(map.clone())));
+    }
+}
+
+/// Without the map the server plays on there is nothing to play, the main menu says why.
+fn leave_on_map_load_failure(
+    mut commands: Commands,
+    mut events: EventReader<MapLoadFailed>,
+    server_state: Option<Res<State<ServerNetworkingState>>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut connection_error: ResMut<ConnectionError>,
+) {
+    for MapLoadFailed { name, error } in events.read() {
+        connection_error.0 = Some(format!("Could not load map {:?}: {}", name, error));
+        if server_state.is_some_and(|state| *state.get() == ServerNetworkingState::Started) {
+            commands.stop_server();
+        }
+        commands.disconnect_client();
+        commands.insert_resource(LeftGame);
+        next_state.set(GameState::MainMenu);
+    }
+}
+
+/// A disconnect while still in the main menu means we never got in.
+///
+/// The auth service already said whether our id is in use, unless we could not reach it.
+fn handle_failed_connection(
+    mut events: EventReader<DisconnectEvent>,
+    connection: Res<ClientConnection>,
+    state: Res<State<GameState>>,
+    left_game: Option<Res<LeftGame>>,
+    mut connection_error: ResMut<ConnectionError>,
+) {
+    for event in events.read() {
+        if *state.get() != GameState::MainMenu
+            || connection_error.0.is_some()
+            || left_game.is_some()
+        {
+            continue;
+        }
+        let message = format!(
+            "Could not connect ({:?}), the server may be down or id {} may already be in use",
+            event.reason,
+            connection.client.id()
+        );
+        error!("{}", message);
+        connection_error.0 = Some(message);
+    }
+}
+
+/// Losing the connection while playing sends us back to the main menu, which shows why.
+fn handle_disconnection(
+    mut events: EventReader<DisconnectEvent>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    left_game: Option<Res<LeftGame>>,
+    pending_reconnect: Option<Res<PendingReconnect>>,
+    mut connection_error: ResMut<ConnectionError>,
+) {
+    for event in events.read() {
+        if *state.get() == GameState::MainMenu || left_game.is_some() || pending_reconnect.is_some()
+        {
+            continue;
+        }
+        let message = format!("Disconnected from the server ({:?})", event.reason);
+        info!("{}", message);
+        // a rejection that came first says better why
+        connection_error.0.get_or_insert(message);
+        next_state.set(GameState::MainMenu);
+    }
+}
+
+/// Q in the lobby or the pause menu leaves the game, a host also shuts its server down for everyone.
+fn leave_game(
+    mut commands: Commands,
+    input: Res<ButtonInput<KeyCode>>,
+    server_state: Option<Res<State<ServerNetworkingState>>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !input.just_pressed(KeyCode::KeyQ) {
+        return;
+    }
+    info!("Leaving the game");
+    if server_state.is_some_and(|state| *state.get() == ServerNetworkingState::Started) {
+        commands.stop_server();
+    }
+    commands.disconnect_client();
+    commands.insert_resource(LeftGame);
+    next_state.set(GameState::MainMenu);
+}
+
+/// A lobby left over from the last server would start the next one straight away.
+fn clear_lobby(mut commands: Commands) {
+    commands.remove_resource::<Lobby>();
+}
+
+/// Whatever the server replicated to us is gone with the connection.
+fn despawn_replicated_entities(
+    mut commands: Commands,
+    query: Query<
+        Entity,
+        Or<(
+            With<Confirmed>,
+            With<Predicted>,
+            With<Interpolated>,
+            With<PrePredicted>,
+        )>,
+    >,
+) {
+    for entity in &query {
+        if let Some(entity) = commands.get_entity(entity) {
+            entity.despawn_recursive();
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use std::{
+    io::ErrorKind,
+    net::{Ipv4Addr, SocketAddr, UdpSocket},
+};
+
+use bevy::prelude::*;
+
+use crate::{
+    lightyear::{
+        discovery::{
+            DiscoveredServer, DiscoveredServers, ServerBeacon, BEACON_TIMEOUT, DISCOVERY_PORT,
+        },
+        settings::NetSettings,
+    },
+    my_states::GameState,
+};
+
+/// Listens for server beacons while in the main menu.
+pub struct MyClientDiscoveryPlugin;
+
+impl Plugin for MyClientDiscoveryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DiscoveredServers>()
+            .add_systems(OnEnter(GameState::MainMenu), start_listening)
+            .add_systems(OnExit(GameState::MainMenu), stop_listening)
+            .add_systems(
+                Update,
+                receive_beacons.run_if(resource_exists::<DiscoverySocket>),
+            );
+    }
+}
+
+#[derive(Resource)]
+struct DiscoverySocket(UdpSocket);
+
+fn start_listening(mut commands: Commands, settings: Res<NetSettings>) {
+    if !settings.lan_discovery {
+        return;
+    }
+
+    let addr = SocketAddr::from((Ipv4Addr::UNSPECIFIED, DISCOVERY_PORT));
+    let socket = UdpSocket::bind(addr).and_then(|socket| {
+        socket.set_nonblocking(true)?;
+        Ok(socket)
+    });
+    match socket {
+        Ok(socket) => commands.insert_resource(DiscoverySocket(socket)),
+        // only one process per machine can listen, usually another game in the menu
+        Err(err) => warn!("Not listening for LAN servers on {}: {}", addr, err),
+    }
+}
+
+fn stop_listening(mut commands: Commands, mut servers: ResMut<DiscoveredServers>) {
+    commands.remove_resource::<DiscoverySocket>();
+    servers.0.clear();
+}
+
+fn receive_beacons(
+    socket: Res<DiscoverySocket>,
+    time: Res<Time>,
+    mut servers: ResMut<DiscoveredServers>,
+) {
+    let now = time.elapsed();
+    let mut buffer = [0; 1024];
+    loop {
+        let (len, sender) = match socket.0.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+            Err(err) => {
+                debug!("Could not receive a LAN beacon: {}", err);
+                break;
+            }
+        };
+        let Some(beacon) = ServerBeacon::decode(&buffer[..len]) else {
+            continue;
+        };
+
+        let addr = SocketAddr::new(sender.ip(), beacon.port);
+        match servers.0.iter().position(|server| server.addr == addr) {
+            // the menu only needs to know when something it shows changed
+            Some(index) if servers.0[index].beacon == beacon => {
+                servers.bypass_change_detection().0[index].last_seen = now;
+            }
+            Some(index) => {
+                servers.0[index] = DiscoveredServer {
+                    addr,
+                    beacon,
+                    last_seen: now,
+                }
+            }
+            None => servers.0.push(DiscoveredServer {
+                addr,
+                beacon,
+                last_seen: now,
+            }),
+        }
+    }
+
+    if servers
+        .0
+        .iter()
+        .any(|server| now - server.last_seen > BEACON_TIMEOUT)
+    {
+        servers
+            .0
+            .retain(|server| now - server.last_seen <= BEACON_TIMEOUT);
+    }
+}
@@ -1,12 +1,26 @@
 use bevy::prelude::*;
+use chat_client::MyClientChatPlugin;
+use checksum_client::MyClientChecksumPlugin;
+use connection_client::MyClientConnectionPlugin;
+use discovery_client::MyClientDiscoveryPlugin;
 use lightyear::{
     client::{config::ClientConfig, plugin::ClientPlugins},
     connection::client,
 };
 use movement_client::MyClientMovementPlugin;
+use net_stats_client::MyClientNetStatsPlugin;
+use recorder::InputRecorderPlugin;
+use rollback_diagnostics::RollbackDiagnosticsPlugin;
 use spawn_player::SpawnPlayerClientPlugin;
 
+mod chat_client;
+mod checksum_client;
+mod connection_client;
+mod discovery_client;
 mod movement_client;
+mod net_stats_client;
+mod recorder;
+mod rollback_diagnostics;
 mod spawn_player;
 
 pub struct MyClientPlugin;
@@ -17,6 +31,13 @@ impl Plugin for MyClientPlugin {
             build_client_plugin(),
             SpawnPlayerClientPlugin,
             MyClientMovementPlugin,
+            MyClientConnectionPlugin,
+            RollbackDiagnosticsPlugin,
+            MyClientChecksumPlugin,
+            InputRecorderPlugin,
+            MyClientDiscoveryPlugin,
+            MyClientChatPlugin,
+            MyClientNetStatsPlugin,
         ));
     }
 }
@@ -1,4 +1,3 @@
-use avian3d::{math::Vector, prelude::*};
 use bevy::prelude::*;
 use leafwing_input_manager::prelude::ActionState;
 use lightyear::{
@@ -14,11 +13,7 @@ use crate::{
         lib::{
             FixedSet, PhysicalPlayerBodyMarker, PhysicalPlayerHeadMarker, PlayerActions, PlayerId,
         },
-        movement::shared_movement,
-        physics::{
-            Grounded, JumpImpulse, MaxMovementSpeed, MaxSlopeAngle, MovementAcceleration,
-            MovementDampingFactor,
-        },
+        movement::{shared_movement, CharacterQuery},
     },
     my_states::InGameUnpaused,
 };
@@ -29,12 +24,9 @@ impl Plugin for MyClientMovementPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(
             FixedUpdate,
-            (
-                movement_client
-                    .run_if(in_state(InGameUnpaused).and_then(not(is_host_server)))
-                    .in_set(FixedSet::Main),
-                (update_grounded, apply_movement_damping).in_set(FixedSet::Physics),
-            ),
+            movement_client
+                .run_if(in_state(InGameUnpaused).and_then(not(is_host_server)))
+                .in_set(FixedSet::Main),
         );
     }
 }
@@ -42,16 +34,9 @@ impl Plugin for MyClientMovementPlugin {
 fn movement_client(
     mut player_body_controller: Query<
         (
-            &Transform,
-            &MovementAcceleration,
-            &MaxMovementSpeed,
-            &JumpImpulse,
-            &mut LinearVelocity,
-            &mut Rotation,
-            &mut PhysicalPlayerBodyMarker,
+            CharacterQuery,
             &ActionState<PlayerActions>,
             &InputBuffer<PlayerActions>,
-            Has<Grounded>,
             &Children,
         ),
         (
@@ -75,20 +60,7 @@ fn movement_client(
         .map(|rb| tick_manager.tick_or_rollback_tick(rb))
         .unwrap_or(tick_manager.tick());
 
-    for (
-        transform,
-        movement_acceleration,
-        max_speed,
-        jump_impulse,
-        mut body_velocity,
-        mut body_rotation,
-        mut phyiscal_body,
-        action_state,
-        input_buffer,
-        is_grounded,
-        children,
-    ) in &mut player_body_controller
-    {
+    for (mut character, action_state, input_buffer, children) in &mut player_body_controller {
         let (head_entity, _, _) = children
             .iter()
             .map(|entity| player_head_query.get(*entity).ok())
@@ -97,109 +69,24 @@ fn movement_client(
             .unwrap();
         let (_, mut head_transform, mut head) = player_head_query.get_mut(head_entity).unwrap();
 
-        if input_buffer.get(tick).is_some() {
-            shared_movement(
-                transform,
-                jump_impulse,
-                movement_acceleration,
-                max_speed,
-                &mut body_velocity,
-                &mut body_rotation,
-                &mut phyiscal_body,
-                &mut head_transform,
-                &mut head,
-                is_grounded,
-                action_state,
-            );
-            continue;
-        }
-
-        if let Some((prev_tick, prev_input)) = input_buffer.get_last_with_tick() {
+        let action_state = if input_buffer.get(tick).is_some() {
+            action_state
+        } else if let Some((prev_tick, prev_input)) = input_buffer.get_last_with_tick() {
             let staleness = (tick - prev_tick).max(0) as u16;
             if staleness > MAX_STALE_TICKS {
                 // input too stale, apply default input (ie, nothing pressed)
-                shared_movement(
-                    transform,
-                    jump_impulse,
-                    movement_acceleration,
-                    max_speed,
-                    &mut body_velocity,
-                    &mut body_rotation,
-                    &mut phyiscal_body,
-                    &mut head_transform,
-                    &mut head,
-                    is_grounded,
-                    action_state,
-                );
+                action_state
             } else {
                 // apply a stale input within our acceptable threshold.
                 // we could use the staleness to decay movement forces as desired.
-                shared_movement(
-                    transform,
-                    jump_impulse,
-                    movement_acceleration,
-                    max_speed,
-                    &mut body_velocity,
-                    &mut body_rotation,
-                    &mut phyiscal_body,
-                    &mut head_transform,
-                    &mut head,
-                    is_grounded,
-                    prev_input,
-                );
+                prev_input
             }
         } else {
             // no inputs in the buffer yet, can happen during initial connection.
             // apply the default input (ie, nothing pressed)
-            shared_movement(
-                transform,
-                jump_impulse,
-                movement_acceleration,
-                max_speed,
-                &mut body_velocity,
-                &mut body_rotation,
-                &mut phyiscal_body,
-                &mut head_transform,
-                &mut head,
-                is_grounded,
-                action_state,
-            );
-        }
-    }
-}
-
-/// Updates the [`Grounded`] status for character controllers.
-fn update_grounded(
-    mut commands: Commands,
-    mut query: Query<
-        (Entity, &ShapeHits, &Rotation, Option<&MaxSlopeAngle>),
-        With<PhysicalPlayerBodyMarker>,
-    >,
-) {
-    for (entity, hits, rotation, max_slope_angle) in &mut query {
-        // The character is grounded if the shape caster has a hit with a normal
-        // that isn't too steep.
-        let is_grounded = hits.iter().any(|hit| {
-            if let Some(angle) = max_slope_angle {
-                (rotation * -hit.normal2).angle_between(Vector::Y).abs() <= angle.0
-            } else {
-                true
-            }
-        });
-
-        if is_grounded {
-            commands.entity(entity).insert(Grounded);
-        } else {
-            commands.entity(entity).remove::<Grounded>();
-        }
-    }
-}
+            action_state
+        };
 
-/// Slows down movement in the XZ plane.
-fn apply_movement_damping(mut query: Query<(&MovementDampingFactor, &mut LinearVelocity)>) {
-    for (damping_factor, mut linear_velocity) in &mut query {
-        // We could use `LinearDamping`, but we don't want to dampen movement along the Y axis
-        linear_velocity.x *= damping_factor.0;
-        linear_velocity.z *= damping_factor.0;
+        shared_movement(&mut character, &mut head_transform, &mut head, action_state);
     }
 }
@@ -0,0 +1,104 @@
+use bevy::{diagnostic::DiagnosticsStore, prelude::*, time::common_conditions::on_timer};
+use lightyear::{
+    prelude::{
+        client::{ClientConfig, MessageEvent},
+        is_host_server, ClientConnectionManager, Tick, TickManager,
+    },
+    transport::io::IoDiagnosticsPlugin,
+};
+
+use crate::{
+    lightyear::net_stats::{Heartbeat, NetSample, NetStats, SAMPLE_INTERVAL},
+    my_states::InGame,
+};
+
+/// Samples the numbers of our connection to the server for the network HUD.
+pub struct MyClientNetStatsPlugin;
+
+impl Plugin for MyClientNetStatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NetStats>()
+            .init_resource::<ServerTicksReceived>()
+            .add_systems(OnEnter(InGame), reset_net_stats)
+            .add_systems(
+                Update,
+                (
+                    count_server_ticks.run_if(not(is_host_server)),
+                    sample_net_stats.run_if(on_timer(SAMPLE_INTERVAL)),
+                )
+                    .chain()
+                    .run_if(in_state(InGame)),
+            );
+    }
+}
+
+/// The server sends a [`Heartbeat`] unreliably on every tick, so the ticks missing
+/// between the ones we receive tell how many packets got lost.
+#[derive(Resource, Default)]
+struct ServerTicksReceived {
+    latest: Option<Tick>,
+    /// Heartbeats received since the last sample.
+    received: u32,
+    /// The latest tick at the last sample, to know how many were sent since.
+    sampled: Option<Tick>,
+}
+
+fn reset_net_stats(mut stats: ResMut<NetStats>, mut ticks: ResMut<ServerTicksReceived>) {
+    stats.0.clear();
+    *ticks = ServerTicksReceived::default();
+}
+
+fn count_server_ticks(
+    mut events: EventReader<MessageEvent<Heartbeat>>,
+    mut ticks: ResMut<ServerTicksReceived>,
+) {
+    for event in events.read() {
+        let tick = event.message().tick;
+        ticks.received += 1;
+        if ticks.latest.map_or(true, |latest| tick > latest) {
+            ticks.latest = Some(tick);
+        }
+    }
+}
+
+fn sample_net_stats(
+    connection_manager: Res<ClientConnectionManager>,
+    client_config: Res<ClientConfig>,
+    tick_manager: Res<TickManager>,
+    time: Res<Time<Fixed>>,
+    diagnostics: Res<DiagnosticsStore>,
+    mut ticks: ResMut<ServerTicksReceived>,
+    mut stats: ResMut<NetStats>,
+) {
+    let rtt = connection_manager.rtt();
+    let tick = tick_manager.tick();
+    let bandwidth = |path| {
+        diagnostics
+            .get(path)
+            .and_then(|diagnostic| diagnostic.smoothed())
+            .unwrap_or_default() as f32
+    };
+
+    let packet_loss_percent = match (ticks.latest, ticks.sampled) {
+        (Some(latest), Some(sampled)) if latest > sampled => {
+            let sent = (latest - sampled) as f32;
+            (1.0 - ticks.received as f32 / sent).clamp(0.0, 1.0) * 100.0
+        }
+        _ => 0.0,
+    };
+    ticks.sampled = ticks.latest;
+    ticks.received = 0;
+
+    stats.push(NetSample {
+        rtt_ms: rtt.as_secs_f32() * 1000.0,
+        jitter_ms: connection_manager.jitter().as_secs_f32() * 1000.0,
+        packet_loss_percent,
+        kb_in_per_sec: bandwidth(&IoDiagnosticsPlugin::BYTES_IN),
+        kb_out_per_sec: bandwidth(&IoDiagnosticsPlugin::BYTES_OUT),
+        tick: tick.0,
+        input_delay_ticks: client_config
+            .prediction
+            .input_delay_ticks(rtt, time.timestep()),
+        ticks_ahead: ticks.latest.map_or(0, |latest| tick - latest),
+    });
+}
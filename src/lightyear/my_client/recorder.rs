@@ -0,0 +1,297 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use avian3d::prelude::*;
+use bevy::{ecs::query::QueryData, prelude::*};
+use leafwing_input_manager::prelude::{ActionState, InputMap};
+use lightyear::{
+    inputs::leafwing::input_buffer::InputBuffer,
+    prelude::{
+        client::{Confirmed, Predicted, Rollback},
+        Tick, TickManager,
+    },
+};
+
+use crate::{
+    lightyear::my_shared::{
+        lib::{
+            FixedSet, PhysicalPlayerBodyMarker, PhysicalPlayerHeadMarker, PlayerActions, PlayerId,
+        },
+        physics::{CharacterControllerMode, JumpTimers, MovementState},
+        recording::{OtherPlayer, PlayerSnapshot, RecordedTick, Recording, ToggleRecording},
+    },
+    map::{MapTick, SelectedMap},
+    my_states::InGame,
+    FIXED_TIMESTEP_HZ,
+};
+
+/// Records the players' inputs while F5 is toggled on, to replay them with the `replay` binary.
+pub struct InputRecorderPlugin;
+
+impl Plugin for InputRecorderPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<InputRecorder>()
+            .add_event::<ToggleRecording>()
+            .add_systems(
+                Update,
+                (toggle_recording_on_f5, toggle_recording)
+                    .chain()
+                    .run_if(in_state(InGame)),
+            )
+            .add_systems(
+                FixedUpdate,
+                (
+                    snapshot_rolled_back_start.before(FixedSet::Main),
+                    record_inputs.after(FixedSet::Physics),
+                )
+                    .run_if(|recorder: Res<InputRecorder>| recorder.0.is_some()),
+            )
+            .add_systems(OnExit(InGame), save_recording);
+    }
+}
+
+#[derive(Resource, Default)]
+struct InputRecorder(Option<Recording>);
+
+/// A predicted player body, ours or someone else's.
+#[derive(QueryData)]
+struct RecordedBody {
+    player_id: &'static PlayerId,
+    position: &'static Position,
+    rotation: &'static Rotation,
+    linear_velocity: &'static LinearVelocity,
+    body: &'static PhysicalPlayerBodyMarker,
+    action_state: &'static ActionState<PlayerActions>,
+    input_buffer: Option<&'static InputBuffer<PlayerActions>>,
+    controller: Option<&'static CharacterControllerMode>,
+    movement_state: &'static MovementState,
+    jump_timers: &'static JumpTimers,
+    children: Option<&'static Children>,
+    predicted: Option<&'static Predicted>,
+}
+
+/// The other players' bodies we predict, not their confirmed or interpolated copies.
+type OtherBodyFilter = (Without<InputMap<PlayerActions>>, With<Predicted>);
+
+impl RecordedBodyItem<'_> {
+    fn snapshot(&self, head_query: &Query<&PhysicalPlayerHeadMarker>) -> PlayerSnapshot {
+        let pitch = self
+            .children
+            .into_iter()
+            .flatten()
+            .find_map(|entity| head_query.get(*entity).ok())
+            .map_or(0.0, |head| head.pitch);
+        PlayerSnapshot {
+            position: self.position.0,
+            rotation: self.rotation.0,
+            linear_velocity: self.linear_velocity.0,
+            yaw: self.body.yaw,
+            pitch,
+            controller: self.controller.copied().unwrap_or_default(),
+            movement_state: *self.movement_state,
+            jump_timers: *self.jump_timers,
+        }
+    }
+
+    /// The inputs the body was simulated with on `tick`.
+    fn action_state(&self, tick: Tick) -> ActionState<PlayerActions> {
+        self.input_buffer
+            .and_then(|input_buffer| input_buffer.get(tick))
+            .unwrap_or(self.action_state)
+            .clone()
+    }
+}
+
+fn toggle_recording_on_f5(
+    input: Res<ButtonInput<KeyCode>>,
+    mut events: EventWriter<ToggleRecording>,
+) {
+    if input.just_pressed(KeyCode::F5) {
+        events.send_default();
+    }
+}
+
+fn toggle_recording(
+    mut events: EventReader<ToggleRecording>,
+    mut recorder: ResMut<InputRecorder>,
+    selected_map: Res<SelectedMap>,
+    map_tick: Res<MapTick>,
+    local_body_query: Query<RecordedBody, With<InputMap<PlayerActions>>>,
+    other_body_query: Query<RecordedBody, OtherBodyFilter>,
+    confirmed_query: Query<&Confirmed>,
+    player_head_query: Query<&PhysicalPlayerHeadMarker>,
+) {
+    let Some(ToggleRecording { path }) = events.read().last() else {
+        return;
+    };
+    if recorder.0.is_some() {
+        save(
+            &mut recorder,
+            path.as_deref(),
+            &local_body_query,
+            &confirmed_query,
+        );
+        return;
+    }
+
+    let Ok(local_body) = local_body_query.get_single() else {
+        warn!("No local player to record");
+        return;
+    };
+    let Some(map) = selected_map.0.clone() else {
+        warn!("No map to record on");
+        return;
+    };
+    let other_players = other_body_query
+        .iter()
+        .map(|body| OtherPlayer {
+            player_id: body.player_id.0,
+            initial_state: body.snapshot(&player_head_query),
+        })
+        .collect();
+
+    info!("Started recording inputs");
+    recorder.0 = Some(Recording {
+        fixed_timestep_hz: FIXED_TIMESTEP_HZ,
+        map,
+        map_tick: map_tick.0,
+        initial_state: local_body.snapshot(&player_head_query),
+        other_players,
+        ticks: Vec::new(),
+    });
+}
+
+/// A rollback simulating the first recorded tick again started it from the server's state,
+/// which is where the replay has to start from too.
+fn snapshot_rolled_back_start(
+    tick_manager: Res<TickManager>,
+    rollback: Option<Res<Rollback>>,
+    mut recorder: ResMut<InputRecorder>,
+    local_body_query: Query<RecordedBody, With<InputMap<PlayerActions>>>,
+    other_body_query: Query<RecordedBody, OtherBodyFilter>,
+    player_head_query: Query<&PhysicalPlayerHeadMarker>,
+) {
+    let Some(recording) = recorder.0.as_mut() else {
+        return;
+    };
+    let tick = rollback
+        .as_ref()
+        .map(|rb| tick_manager.tick_or_rollback_tick(rb))
+        .unwrap_or(tick_manager.tick());
+    if tick != recording.map_tick + 1 {
+        return;
+    }
+    let Ok(local_body) = local_body_query.get_single() else {
+        return;
+    };
+    recording.initial_state = local_body.snapshot(&player_head_query);
+    for player in &mut recording.other_players {
+        if let Some(body) = other_body_query
+            .iter()
+            .find(|body| body.player_id.0 == player.player_id)
+        {
+            player.initial_state = body.snapshot(&player_head_query);
+        }
+    }
+}
+
+fn record_inputs(
+    tick_manager: Res<TickManager>,
+    rollback: Option<Res<Rollback>>,
+    mut recorder: ResMut<InputRecorder>,
+    local_body_query: Query<RecordedBody, With<InputMap<PlayerActions>>>,
+    other_body_query: Query<RecordedBody, OtherBodyFilter>,
+) {
+    let Some(recording) = recorder.0.as_mut() else {
+        return;
+    };
+    let Ok(local_body) = local_body_query.get_single() else {
+        return;
+    };
+    let tick = rollback
+        .as_ref()
+        .map(|rb| tick_manager.tick_or_rollback_tick(rb))
+        .unwrap_or(tick_manager.tick());
+
+    let other_action_states = recording
+        .other_players
+        .iter()
+        .map(|player| {
+            other_body_query
+                .iter()
+                .find(|body| body.player_id.0 == player.player_id)
+                // the player left, nobody presses anything on its body anymore
+                .map_or_else(ActionState::default, |body| body.action_state(tick))
+        })
+        .collect();
+    let recorded = RecordedTick {
+        tick,
+        action_state: local_body.action_state(tick),
+        position: local_body.position.0,
+        other_action_states,
+    };
+
+    // a rollback simulates ticks again from the server's state, what we predicted before is replaced
+    match tick - (recording.map_tick + 1) {
+        // the rollback went back to before the recording started, `snapshot_rolled_back_start`
+        // takes the state the first recorded tick is simulated from again
+        index if index < 0 => {}
+        index if (index as usize) < recording.ticks.len() => {
+            recording.ticks[index as usize] = recorded;
+        }
+        _ => recording.ticks.push(recorded),
+    }
+}
+
+fn save_recording(
+    mut recorder: ResMut<InputRecorder>,
+    local_body_query: Query<RecordedBody, With<InputMap<PlayerActions>>>,
+    confirmed_query: Query<&Confirmed>,
+) {
+    if recorder.0.is_some() {
+        save(&mut recorder, None, &local_body_query, &confirmed_query);
+    }
+}
+
+fn save(
+    recorder: &mut InputRecorder,
+    path: Option<&Path>,
+    local_body_query: &Query<RecordedBody, With<InputMap<PlayerActions>>>,
+    confirmed_query: &Query<&Confirmed>,
+) {
+    let Some(mut recording) = recorder.0.take() else {
+        return;
+    };
+    // the ticks the server did not confirm yet could still be corrected by a rollback
+    let confirmed_tick = local_body_query
+        .get_single()
+        .ok()
+        .and_then(|body| body.predicted?.confirmed_entity)
+        .and_then(|entity| confirmed_query.get(entity).ok())
+        .map(|confirmed| confirmed.tick);
+    if let Some(confirmed_tick) = confirmed_tick {
+        recording
+            .ticks
+            .retain(|recorded| recorded.tick <= confirmed_tick);
+    }
+    let path = path.map_or_else(
+        || {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_secs());
+            PathBuf::from(format!("recordings/recording-{}.ron", timestamp))
+        },
+        Path::to_path_buf,
+    );
+
+    match recording.save(&path) {
+        Ok(()) => info!(
+            "Saved {} recorded ticks to {:?}",
+            recording.ticks.len(),
+            path
+        ),
+        Err(err) => error!("Could not save the recording to {:?}: {}", path, err),
+    }
+}
@@ -0,0 +1,307 @@
+use std::{any::type_name, collections::VecDeque, time::Duration};
+
+use avian3d::prelude::*;
+use bevy::{
+    color::palettes::css,
+    diagnostic::{Diagnostic, DiagnosticPath, Diagnostics, RegisterDiagnostic},
+    prelude::*,
+};
+use lightyear::prelude::{
+    client::{Confirmed, Predicted, PredictionSet, Rollback},
+    MainSet, Tick, TickManager,
+};
+
+use crate::{
+    lightyear::my_shared::{
+        lib::{PhysicalPlayerBodyMarker, PhysicalPlayerHeadMarker},
+        physics::{GroundVelocity, JumpTimers, MovementState},
+    },
+    my_states::InGame,
+};
+
+/// How many ticks of predicted values we keep to compare against the server.
+const HISTORY_TICKS: usize = 128;
+
+/// Counts rollbacks and finds out which predicted component disagreed with the server.
+pub struct RollbackDiagnosticsPlugin;
+
+impl RollbackDiagnosticsPlugin {
+    pub const ROLLBACKS_PER_SECOND: DiagnosticPath =
+        DiagnosticPath::const_new("rollback/rollbacks_per_second");
+    pub const ROLLBACK_DEPTH: DiagnosticPath = DiagnosticPath::const_new("rollback/depth");
+    pub const MISMATCHES: DiagnosticPath = DiagnosticPath::const_new("rollback/mismatches");
+}
+
+impl Plugin for RollbackDiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RollbackStats>()
+            .init_resource::<RollbackOverlayVisible>()
+            .register_diagnostic(Diagnostic::new(Self::ROLLBACKS_PER_SECOND))
+            .register_diagnostic(Diagnostic::new(Self::ROLLBACK_DEPTH).with_suffix(" ticks"))
+            .register_diagnostic(Diagnostic::new(Self::MISMATCHES))
+            .add_systems(FixedFirst, track_rollbacks)
+            .add_systems(OnEnter(InGame), (reset_rollback_stats, spawn_overlay))
+            .add_systems(
+                Update,
+                (
+                    measure_rollbacks_per_second,
+                    toggle_overlay,
+                    update_overlay.run_if(in_state(InGame)),
+                ),
+            );
+
+        add_mismatch_check::<Position>(app);
+        add_mismatch_check::<Rotation>(app);
+        add_mismatch_check::<LinearVelocity>(app);
+        add_mismatch_check::<PhysicalPlayerBodyMarker>(app);
+        add_mismatch_check::<PhysicalPlayerHeadMarker>(app);
+        add_mismatch_check::<MovementState>(app);
+        add_mismatch_check::<JumpTimers>(app);
+        add_mismatch_check::<GroundVelocity>(app);
+    }
+}
+
+fn add_mismatch_check<C: Component + PartialEq + Clone>(app: &mut App) {
+    app.add_systems(FixedPostUpdate, record_predicted_history::<C>)
+        .add_systems(
+            PreUpdate,
+            check_mismatch::<C>
+                .after(MainSet::Receive)
+                .before(PredictionSet::Rollback),
+        );
+}
+
+/// A predicted component that differed from the server's confirmed value.
+#[derive(Debug, Clone)]
+pub struct Mismatch {
+    pub component: &'static str,
+    pub entity: Entity,
+    pub tick: Tick,
+}
+
+#[derive(Resource, Default, Debug)]
+pub struct RollbackStats {
+    pub total_rollbacks: u32,
+    pub rollbacks_last_second: u32,
+    pub last_depth: u16,
+    pub max_depth: u16,
+    pub total_mismatches: u32,
+    pub last_mismatch: Option<Mismatch>,
+    /// The tick the rollback we are currently replaying started from.
+    rollback_start: Option<Tick>,
+    rollbacks_this_second: u32,
+    second_started: Duration,
+}
+
+/// Predicted values of `C` for the last [`HISTORY_TICKS`] ticks.
+#[derive(Component)]
+struct PredictedHistory<C>(VecDeque<(Tick, C)>);
+
+fn reset_rollback_stats(mut stats: ResMut<RollbackStats>) {
+    *stats = RollbackStats::default();
+}
+
+// A rollback replays the fixed schedule from an older tick up to the current one,
+// so a rollback starts whenever the replayed tick jumps backwards.
+fn track_rollbacks(
+    tick_manager: Res<TickManager>,
+    rollback: Option<Res<Rollback>>,
+    mut stats: ResMut<RollbackStats>,
+    mut diagnostics: Diagnostics,
+) {
+    let tick = tick_manager.tick();
+    let rollback_tick = rollback
+        .as_ref()
+        .map(|rb| tick_manager.tick_or_rollback_tick(rb))
+        .unwrap_or(tick);
+
+    if rollback_tick == tick {
+        stats.rollback_start = None;
+        return;
+    }
+
+    let is_new_rollback = stats
+        .rollback_start
+        .map_or(true, |start| rollback_tick <= start);
+    if is_new_rollback {
+        let depth = (tick - rollback_tick).max(0) as u16;
+        stats.total_rollbacks += 1;
+        stats.rollbacks_this_second += 1;
+        stats.last_depth = depth;
+        stats.max_depth = stats.max_depth.max(depth);
+        diagnostics.add_measurement(&RollbackDiagnosticsPlugin::ROLLBACK_DEPTH, || depth as f64);
+    }
+    stats.rollback_start = Some(rollback_tick);
+}
+
+fn measure_rollbacks_per_second(
+    time: Res<Time>,
+    mut stats: ResMut<RollbackStats>,
+    mut diagnostics: Diagnostics,
+) {
+    if time.elapsed() - stats.second_started < Duration::from_secs(1) {
+        return;
+    }
+
+    stats.second_started = time.elapsed();
+    stats.rollbacks_last_second = stats.rollbacks_this_second;
+    stats.rollbacks_this_second = 0;
+    let rollbacks = stats.rollbacks_last_second;
+    diagnostics.add_measurement(&RollbackDiagnosticsPlugin::ROLLBACKS_PER_SECOND, || {
+        rollbacks as f64
+    });
+}
+
+fn record_predicted_history<C: Component + Clone>(
+    mut commands: Commands,
+    tick_manager: Res<TickManager>,
+    rollback: Option<Res<Rollback>>,
+    mut query: Query<(Entity, &C, Option<&mut PredictedHistory<C>>), With<Predicted>>,
+) {
+    // get the tick, even if during rollback
+    let tick = rollback
+        .as_ref()
+        .map(|rb| tick_manager.tick_or_rollback_tick(rb))
+        .unwrap_or(tick_manager.tick());
+
+    for (entity, component, history) in &mut query {
+        let Some(mut history) = history else {
+            commands
+                .entity(entity)
+                .insert(PredictedHistory(VecDeque::from([(
+                    tick,
+                    component.clone(),
+                )])));
+            continue;
+        };
+
+        // a rollback rewrites the ticks it replays
+        while history.0.back().is_some_and(|(t, _)| *t >= tick) {
+            history.0.pop_back();
+        }
+        history.0.push_back((tick, component.clone()));
+        if history.0.len() > HISTORY_TICKS {
+            history.0.pop_front();
+        }
+    }
+}
+
+fn check_mismatch<C: Component + PartialEq>(
+    confirmed_query: Query<(&Confirmed, &C), Changed<C>>,
+    predicted_query: Query<&PredictedHistory<C>>,
+    mut stats: ResMut<RollbackStats>,
+    mut diagnostics: Diagnostics,
+) {
+    let mut mismatches = 0;
+    for (confirmed, confirmed_component) in &confirmed_query {
+        let Some(predicted_entity) = confirmed.predicted else {
+            continue;
+        };
+        let Ok(history) = predicted_query.get(predicted_entity) else {
+            continue;
+        };
+        let Some((_, predicted_component)) = history.0.iter().find(|(t, _)| *t == confirmed.tick)
+        else {
+            continue;
+        };
+        if predicted_component == confirmed_component {
+            continue;
+        }
+
+        let component = short_type_name::<C>();
+        debug!(
+            "Mismatch on {} of {:?} at tick {:?}",
+            component, predicted_entity, confirmed.tick
+        );
+        mismatches += 1;
+        stats.total_mismatches += 1;
+        stats.last_mismatch = Some(Mismatch {
+            component,
+            entity: predicted_entity,
+            tick: confirmed.tick,
+        });
+    }
+
+    if mismatches > 0 {
+        diagnostics.add_measurement(&RollbackDiagnosticsPlugin::MISMATCHES, || mismatches as f64);
+    }
+}
+
+fn short_type_name<C>() -> &'static str {
+    let name = type_name::<C>();
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+#[derive(Resource)]
+struct RollbackOverlayVisible(bool);
+
+impl Default for RollbackOverlayVisible {
+    fn default() -> Self {
+        Self(true)
+    }
+}
+
+#[derive(Component)]
+struct RollbackOverlayText;
+
+fn spawn_overlay(mut commands: Commands) {
+    commands.spawn((
+        Name::new("RollbackOverlay"),
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 16.0,
+                color: css::ORANGE.into(),
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.0),
+            left: Val::Px(10.0),
+            ..default()
+        }),
+        StateScoped(InGame),
+        RollbackOverlayText,
+    ));
+}
+
+fn toggle_overlay(input: Res<ButtonInput<KeyCode>>, mut visible: ResMut<RollbackOverlayVisible>) {
+    if input.just_pressed(KeyCode::F4) {
+        visible.0 = !visible.0;
+    }
+}
+
+fn update_overlay(
+    stats: Res<RollbackStats>,
+    visible: Res<RollbackOverlayVisible>,
+    mut overlay_query: Query<(&mut Text, &mut Visibility), With<RollbackOverlayText>>,
+) {
+    for (mut text, mut visibility) in &mut overlay_query {
+        visibility.set_if_neq(if visible.0 {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        });
+        if !stats.is_changed() {
+            continue;
+        }
+
+        let last_mismatch = match &stats.last_mismatch {
+            Some(mismatch) => format!(
+                "{} on {:?} at tick {:?}",
+                mismatch.component, mismatch.entity, mismatch.tick
+            ),
+            None => "none".to_string(),
+        };
+        text.sections[0].value = format!(
+            "Rollbacks/s: {} (total {})\nRollback depth: {} (max {})\nMismatches: {}, last: {}",
+            stats.rollbacks_last_second,
+            stats.total_rollbacks,
+            stats.last_depth,
+            stats.max_depth,
+            stats.total_mismatches,
+            last_mismatch,
+        );
+    }
+}
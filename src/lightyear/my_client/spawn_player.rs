@@ -1,13 +1,23 @@
 use avian3d::prelude::*;
-use bevy::{color::palettes::css, prelude::*};
+use bevy::{color::palettes::css, ecs::query::QueryData, prelude::*};
 use leafwing_input_manager::prelude::*;
-use lightyear::prelude::client::{ClientConnection, Interpolated, NetClient, Predicted};
+use lightyear::prelude::{
+    client::{ClientConnection, Correction, Interpolated, NetClient, Predicted, PredictionSet},
+    ClientConnectionManager,
+};
 
+use super::connection_client::Session;
 use crate::{
-    lightyear::my_shared::lib::{
-        PhysicalPlayerBodyBundle, PhysicalPlayerBodyMarker, PhysicalPlayerHeadBundle,
-        PhysicalPlayerHeadMarker, PhysicsBundle, PlayerActions, PlayerId,
+    lightyear::{
+        my_shared::lib::{
+            ground_caster, Channel1, PhysicalPlayerBodyBundle, PhysicalPlayerBodyMarker,
+            PhysicalPlayerHeadBundle, PhysicalPlayerHeadMarker, PhysicsBundle, PlayerActions,
+            PlayerId, Respawn, TeleportCount,
+        },
+        my_shared::physics::MovementState,
+        settings::NetSettings,
     },
+    map::MapRoot,
     my_states::InGame,
 };
 
@@ -15,36 +25,94 @@ pub struct SpawnPlayerClientPlugin;
 
 impl Plugin for SpawnPlayerClientPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(OnEnter(InGame), spawn_physical_player)
-            .add_systems(Update, add_non_replicated_to_players);
+        app.add_systems(
+            Update,
+            (
+                spawn_physical_player.run_if(in_state(InGame)),
+                add_non_replicated_to_players,
+                adopt_resumed_player,
+                request_respawn.run_if(in_state(InGame)),
+            ),
+        )
+        .add_systems(PreUpdate, snap_teleports.after(PredictionSet::Rollback));
     }
 }
 
-fn spawn_physical_player(connection: Res<ClientConnection>, mut commands: Commands) {
+fn player_input_map() -> InputMap<PlayerActions> {
+    InputMap::new([
+        (PlayerActions::Jump, KeyCode::Space),
+        (PlayerActions::Sprint, KeyCode::ShiftLeft),
+        (PlayerActions::Crouch, KeyCode::ControlLeft),
+    ])
+    .with_dual_axis(
+        PlayerActions::Move,
+        KeyboardVirtualDPad::new(KeyCode::KeyW, KeyCode::KeyS, KeyCode::KeyA, KeyCode::KeyD),
+    )
+    .with_dual_axis(PlayerActions::LookAround, MouseMove::default())
+}
+
+/// Spawns our player once the server started a new session for us and the map is built,
+/// the body would fall through the floor if it got there before the map.
+fn spawn_physical_player(
+    connection: Res<ClientConnection>,
+    settings: Res<NetSettings>,
+    mut session: ResMut<Session>,
+    map_query: Query<(), With<MapRoot>>,
+    mut commands: Commands,
+) {
+    if !session.needs_body || map_query.is_empty() {
+        return;
+    }
+    session.needs_body = false;
+
     commands
         .spawn((
             PhysicalPlayerBodyBundle::new(
-                InputMap::new([(PlayerActions::Jump, KeyCode::Space)])
-                    .with_dual_axis(
-                        PlayerActions::Move,
-                        KeyboardVirtualDPad::new(
-                            KeyCode::KeyW,
-                            KeyCode::KeyS,
-                            KeyCode::KeyA,
-                            KeyCode::KeyD,
-                        ),
-                    )
-                    .with_dual_axis(PlayerActions::LookAround, MouseMove::default()),
-                Collider::capsule(0.4, 1.0),
+                player_input_map(),
+                MovementState::default().collider(),
                 connection.client.id(),
             ),
+            // the server moves it to a spawn point as soon as it receives it
             SpatialBundle::from_transform(Transform::from_xyz(0.0, 5.0, 0.0)),
+            settings.controller,
         ))
         .with_children(|commands| {
             commands.spawn(PhysicalPlayerHeadBundle::new(connection.client.id()));
         });
 }
 
+/// A resumed session gives us back the player we had before disconnecting, which arrives as a
+/// regular predicted entity: it only needs our inputs and the physics that are not replicated.
+fn adopt_resumed_player(
+    connection: Res<ClientConnection>,
+    mut commands: Commands,
+    player_query: Query<
+        (Entity, &PlayerId),
+        (
+            Added<Predicted>,
+            With<PhysicalPlayerBodyMarker>,
+            Without<InputMap<PlayerActions>>,
+        ),
+    >,
+) {
+    let client_id = connection.client.id();
+    for (entity, player_id) in &player_query {
+        if player_id.0 != client_id {
+            continue;
+        }
+        info!("Taking back our player entity: {:?}", entity);
+        let physics = PhysicsBundle::player();
+        commands.entity(entity).insert((
+            ground_caster(physics.collider.clone()),
+            physics,
+            InputManagerBundle::<PlayerActions> {
+                action_state: ActionState::default(),
+                input_map: player_input_map(),
+            },
+        ));
+    }
+}
+
 /// When we receive other players (whether they are predicted or interpolated), we want to add the physics components
 /// so that our predicted entities can predict collisions with them correctly
 fn add_non_replicated_to_players(
@@ -98,3 +166,51 @@ fn add_non_replicated_to_players(
         },));
     }
 }
+
+fn request_respawn(
+    input: Res<ButtonInput<KeyCode>>,
+    mut connection_manager: ResMut<ClientConnectionManager>,
+) {
+    if input.just_pressed(KeyCode::KeyK) {
+        let _ = connection_manager.send_message::<Channel1, _>(&mut Respawn);
+    }
+}
+
+/// The last [`TeleportCount`] a predicted body was shown with.
+#[derive(Component)]
+struct SeenTeleportCount(u16);
+
+#[derive(QueryData)]
+#[query_data(mutable)]
+struct TeleportQuery {
+    entity: Entity,
+    teleport_count: &'static TeleportCount,
+    seen: Option<&'static mut SeenTeleportCount>,
+    position: &'static Position,
+    rotation: &'static Rotation,
+    position_correction: Option<&'static mut Correction<Position>>,
+    rotation_correction: Option<&'static mut Correction<Rotation>>,
+}
+
+/// A rollback into a teleport would otherwise smooth the body across the map. Starting the
+/// correction from the corrected state makes every visual step land where the server put it.
+fn snap_teleports(mut commands: Commands, mut query: Query<TeleportQuery, With<Predicted>>) {
+    for body in &mut query {
+        let Some(mut seen) = body.seen else {
+            commands
+                .entity(body.entity)
+                .insert(SeenTeleportCount(body.teleport_count.0));
+            continue;
+        };
+        if seen.0 == body.teleport_count.0 {
+            continue;
+        }
+        seen.0 = body.teleport_count.0;
+        if let Some(mut correction) = body.position_correction {
+            correction.original_prediction = *body.position;
+        }
+        if let Some(mut correction) = body.rotation_correction {
+            correction.original_prediction = *body.rotation;
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    time::Duration,
+};
+
+use bevy::{prelude::*, tasks::IoTaskPool, utils::HashSet};
+use lightyear::{
+    connection::netcode::ConnectToken,
+    prelude::{server::NetworkingState as ServerNetworkingState, ClientId},
+};
+
+use super::connection_server::ConnectedClients;
+use crate::lightyear::settings::{AuthMode, NetSettings};
+
+/// How long a client gets to talk to the auth service before we give up on it.
+const STREAM_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// A stand-in for a real auth backend: with token authentication anyone connecting over TCP
+/// gets a connect token for a fresh client id, signed with the server's private key.
+///
+/// With manual authentication clients pick their own id, so instead they send it and get told
+/// whether it is in use: netcode silently ignores them if it is.
+pub struct MyServerAuthPlugin;
+
+impl Plugin for MyServerAuthPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(ServerNetworkingState::Started), start_auth_service)
+            .add_systems(OnExit(ServerNetworkingState::Started), stop_auth_service)
+            .add_systems(
+                Update,
+                handle_auth_requests.run_if(resource_exists::<AuthService>),
+            );
+    }
+}
+
+#[derive(Resource)]
+struct AuthService(TcpListener);
+
+fn start_auth_service(mut commands: Commands, settings: Res<NetSettings>) {
+    if let Err(err) = settings.check_server() {
+        error!("Not starting the auth service: {}", err);
+        return;
+    }
+
+    let addr = settings.auth_bind_addr();
+    let listener = TcpListener::bind(addr).and_then(|listener| {
+        listener.set_nonblocking(true)?;
+        Ok(listener)
+    });
+    match listener {
+        Ok(listener) => {
+            info!("Auth service listening on {}", addr);
+            commands.insert_resource(AuthService(listener));
+        }
+        Err(err) => error!("Could not start the auth service on {}: {}", addr, err),
+    }
+}
+
+fn stop_auth_service(mut commands: Commands) {
+    commands.remove_resource::<AuthService>();
+}
+
+fn handle_auth_requests(
+    auth_service: Res<AuthService>,
+    settings: Res<NetSettings>,
+    clients: Res<ConnectedClients>,
+) {
+    loop {
+        let (stream, addr) = match auth_service.0.accept() {
+            Ok(connection) => connection,
+            Err(err) if err.kind() == ErrorKind::WouldBlock => return,
+            Err(err) => {
+                error!("Auth service failed to accept a connection: {}", err);
+                return;
+            }
+        };
+        match settings.auth {
+            AuthMode::Token => issue_connect_token(stream, addr, &settings, &clients),
+            AuthMode::Manual => check_client_id(stream, addr, clients.0.clone()),
+        }
+    }
+}
+
+fn issue_connect_token(
+    mut stream: TcpStream,
+    addr: SocketAddr,
+    settings: &NetSettings,
+    clients: &ConnectedClients,
+) {
+    // both checked when the service started
+    let (Ok(private_key), Ok(server_addr)) = (settings.server_key(), settings.token_server_addr())
+    else {
+        return;
+    };
+    let client_id = loop {
+        let client_id = rand::random();
+        if !clients.0.contains(&ClientId::Netcode(client_id)) {
+            break client_id;
+        }
+    };
+    let token = ConnectToken::build(server_addr, settings.protocol_id, client_id, private_key)
+        .generate()
+        .map_err(|err| format!("{:?}", err))
+        .and_then(|token| token.try_into_bytes().map_err(|err| format!("{:?}", err)));
+
+    let bytes = match token {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            error!("Could not build a connect token for {}: {}", addr, err);
+            return;
+        }
+    };
+
+    // a slow client must not hold up the server's tick, so the token is written on the side
+    IoTaskPool::get()
+        .spawn(async move {
+            let sent = stream
+                .set_nonblocking(false)
+                .and_then(|_| stream.set_write_timeout(Some(STREAM_TIMEOUT)))
+                .and_then(|_| stream.write_all(&bytes));
+            match sent {
+                Ok(()) => info!(
+                    "Issued a connect token for client {} to {}",
+                    client_id, addr
+                ),
+                Err(err) => error!("Could not issue a connect token to {}: {}", addr, err),
+            }
+        })
+        .detach();
+}
+
+/// Answers a single byte, 0 if the id the client sent is in use. The clients connected right
+/// now are all we go by, one connecting meanwhile still gets in first.
+fn check_client_id(mut stream: TcpStream, addr: SocketAddr, clients: HashSet<ClientId>) {
+    IoTaskPool::get()
+        .spawn(async move {
+            let answered = stream
+                .set_nonblocking(false)
+                .and_then(|_| stream.set_read_timeout(Some(STREAM_TIMEOUT)))
+                .and_then(|_| stream.set_write_timeout(Some(STREAM_TIMEOUT)))
+                .and_then(|_| {
+                    let mut client_id = [0u8; 8];
+                    stream.read_exact(&mut client_id)?;
+                    let client_id = u64::from_le_bytes(client_id);
+                    let id_free = !clients.contains(&ClientId::Netcode(client_id));
+                    stream.write_all(&[id_free as u8])?;
+                    Ok((client_id, id_free))
+                });
+            match answered {
+                Ok((client_id, true)) => info!("Id {} of {} is free", client_id, addr),
+                Ok((client_id, false)) => {
+                    warn!("Told {} that id {} is already in use", addr, client_id)
+                }
+                Err(err) => error!("Could not check the id of {}: {}", addr, err),
+            }
+        })
+        .detach();
+}
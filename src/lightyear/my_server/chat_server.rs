@@ -0,0 +1,104 @@
+use std::{collections::VecDeque, time::Duration};
+
+use bevy::{prelude::*, utils::HashMap};
+use lightyear::prelude::{
+    server::{DisconnectEvent, NetworkingState as ServerNetworkingState},
+    ClientId, NetworkTarget, ServerConnectionManager, ServerMessageEvent,
+};
+
+use crate::lightyear::my_shared::{
+    chat::{sanitize_chat_message, ChatBroadcast, ChatMessage},
+    lib::{Channel1, PlayerId},
+};
+
+/// How many messages a client may send within [`CHAT_RATE_WINDOW`].
+const CHAT_RATE_LIMIT: usize = 5;
+const CHAT_RATE_WINDOW: Duration = Duration::from_secs(5);
+
+/// Checks chat messages and passes the good ones on to everyone.
+pub struct MyServerChatPlugin;
+
+impl Plugin for MyServerChatPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChatRateLimits>()
+            .add_systems(
+                Update,
+                (forget_disconnected_chatters, relay_chat_messages)
+                    .run_if(in_state(ServerNetworkingState::Started)),
+            )
+            .add_systems(OnExit(ServerNetworkingState::Started), clear_rate_limits);
+    }
+}
+
+/// When each client sent its recent chat messages.
+#[derive(Resource, Default)]
+struct ChatRateLimits(HashMap<ClientId, VecDeque<Duration>>);
+
+impl ChatRateLimits {
+    /// Records a message sent now, unless the client already used up its allowance.
+    fn allow(&mut self, client_id: ClientId, now: Duration) -> bool {
+        let sent = self.0.entry(client_id).or_default();
+        while sent
+            .front()
+            .is_some_and(|&sent_at| now - sent_at >= CHAT_RATE_WINDOW)
+        {
+            sent.pop_front();
+        }
+        if sent.len() >= CHAT_RATE_LIMIT {
+            return false;
+        }
+        sent.push_back(now);
+        true
+    }
+}
+
+fn relay_chat_messages(
+    mut events: EventReader<ServerMessageEvent<ChatMessage>>,
+    mut connection_manager: ResMut<ServerConnectionManager>,
+    mut rate_limits: ResMut<ChatRateLimits>,
+    time: Res<Time>,
+) {
+    for event in events.read() {
+        let client_id = *event.context();
+        let ChatMessage(text) = event.message();
+
+        let Some(text) = sanitize_chat_message(text) else {
+            warn!(
+                "Dropping an empty or too long chat message from {:?}",
+                client_id
+            );
+            continue;
+        };
+        if !rate_limits.allow(client_id, time.elapsed()) {
+            warn!(
+                "Dropping a chat message from {:?}: sending too fast",
+                client_id
+            );
+            continue;
+        }
+
+        info!("Chat {:?}: {}", client_id, text);
+        if let Err(err) = connection_manager.send_message_to_target::<Channel1, _>(
+            &mut ChatBroadcast {
+                sender: PlayerId(client_id),
+                text,
+            },
+            NetworkTarget::All,
+        ) {
+            error!("Could not send a chat message: {:?}", err);
+        }
+    }
+}
+
+fn forget_disconnected_chatters(
+    mut events: EventReader<DisconnectEvent>,
+    mut rate_limits: ResMut<ChatRateLimits>,
+) {
+    for event in events.read() {
+        rate_limits.0.remove(&event.client_id);
+    }
+}
+
+fn clear_rate_limits(mut rate_limits: ResMut<ChatRateLimits>) {
+    rate_limits.0.clear();
+}
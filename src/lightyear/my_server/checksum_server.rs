@@ -0,0 +1,67 @@
+use avian3d::prelude::*;
+use bevy::prelude::*;
+use lightyear::prelude::{
+    server::NetworkingState as ServerNetworkingState, NetworkTarget, ServerConnectionManager,
+    TickManager,
+};
+
+use crate::lightyear::my_shared::{
+    checksum::{ChecksumChannel, ChecksumMessage, PlayerChecksum},
+    lib::{FixedSet, PhysicalPlayerBodyMarker, PhysicalPlayerHeadMarker, PlayerId},
+};
+
+pub struct MyServerChecksumPlugin;
+
+impl Plugin for MyServerChecksumPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            send_checksums
+                .after(FixedSet::Physics)
+                .run_if(in_state(ServerNetworkingState::Started)),
+        );
+    }
+}
+
+fn send_checksums(
+    tick_manager: Res<TickManager>,
+    mut connection: ResMut<ServerConnectionManager>,
+    player_body_query: Query<(
+        &PlayerId,
+        &Position,
+        &Rotation,
+        &LinearVelocity,
+        &PhysicalPlayerBodyMarker,
+        Option<&Children>,
+    )>,
+    player_head_query: Query<&PhysicalPlayerHeadMarker>,
+) {
+    let players: Vec<_> = player_body_query
+        .iter()
+        .map(
+            |(player_id, position, rotation, linear_velocity, body, children)| {
+                let head = children
+                    .into_iter()
+                    .flatten()
+                    .find_map(|entity| player_head_query.get(*entity).ok());
+                (
+                    player_id.0,
+                    PlayerChecksum::new(position, rotation, linear_velocity, body, head),
+                )
+            },
+        )
+        .collect();
+    if players.is_empty() {
+        return;
+    }
+
+    let mut message = ChecksumMessage {
+        tick: tick_manager.tick(),
+        players,
+    };
+    if let Err(err) =
+        connection.send_message_to_target::<ChecksumChannel, _>(&mut message, NetworkTarget::All)
+    {
+        error!("Could not send checksums: {:?}", err);
+    }
+}
@@ -0,0 +1,223 @@
+use std::time::Duration;
+
+use avian3d::{math::Vector, prelude::*};
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use leafwing_input_manager::prelude::ActionState;
+use lightyear::prelude::{
+    server::{
+        ConnectEvent, DisconnectEvent, NetworkingState as ServerNetworkingState, ServerConnections,
+    },
+    ClientId, ServerConnectionManager,
+};
+
+use super::session_server::Sessions;
+use crate::{
+    lightyear::{
+        my_shared::lib::{
+            Channel1, ConnectionRejected, FixedSet, MapSelection, PhysicalPlayerBodyMarker,
+            PlayerActions, PlayerId,
+        },
+        settings::NetSettings,
+    },
+    map::SelectedMap,
+};
+
+pub struct MyServerConnectionPlugin;
+
+impl Plugin for MyServerConnectionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ConnectedClients>()
+            .init_resource::<PendingKicks>()
+            .add_systems(
+                Update,
+                (
+                    handle_connections,
+                    handle_disconnections,
+                    despawn_abandoned_players,
+                    disconnect_kicked_clients,
+                )
+                    .run_if(in_state(ServerNetworkingState::Started)),
+            )
+            .add_systems(
+                FixedUpdate,
+                freeze_disconnected_players
+                    .run_if(in_state(ServerNetworkingState::Started))
+                    .before(FixedSet::Main),
+            )
+            .add_systems(OnEnter(ServerNetworkingState::Started), select_map)
+            // the server shutting down takes every player with it
+            .add_systems(
+                OnExit(ServerNetworkingState::Started),
+                clear_connected_clients,
+            );
+    }
+}
+
+/// The ids of all the clients currently connected to the server.
+#[derive(Resource, Default, Debug)]
+pub(crate) struct ConnectedClients(pub(crate) HashSet<ClientId>);
+
+// Netcode ignores connection requests for an id that is in use, so every connection has its own
+// id: the auth service is where a client finds out its id is taken, before it connects.
+fn handle_connections(
+    mut events: EventReader<ConnectEvent>,
+    mut clients: ResMut<ConnectedClients>,
+    selected_map: Res<SelectedMap>,
+    mut connection_manager: ResMut<ServerConnectionManager>,
+) {
+    for event in events.read() {
+        let client_id = event.client_id;
+        info!("Client connected: {:?}", client_id);
+        clients.0.insert(client_id);
+        if let Some(map) = &selected_map.0 {
+            let _ = connection_manager
+                .send_message::<Channel1, _>(client_id, &mut MapSelection(map.clone()));
+        }
+    }
+}
+
+/// The server plays on the map from its settings, which the host picked in the main menu.
+fn select_map(settings: Res<NetSettings>, mut selected_map: ResMut<SelectedMap>) {
+    info!("Playing on map {:?}", settings.map);
+    selected_map.0 = Some(settings.map.clone());
+}
+
+/// The body of a player whose client disconnected, despawned once the grace period is over
+/// unless the client resumes its session first.
+#[derive(Component, Debug)]
+pub(super) struct DisconnectedPlayer {
+    despawn_at: Duration,
+}
+
+fn handle_disconnections(
+    mut commands: Commands,
+    mut events: EventReader<DisconnectEvent>,
+    mut clients: ResMut<ConnectedClients>,
+    mut pending_kicks: ResMut<PendingKicks>,
+    mut sessions: ResMut<Sessions>,
+    settings: Res<NetSettings>,
+    time: Res<Time>,
+    player_query: Query<(Entity, &PlayerId), With<PhysicalPlayerBodyMarker>>,
+) {
+    for event in events.read() {
+        info!("Client disconnected: {:?}", event.client_id);
+        clients.0.remove(&event.client_id);
+        pending_kicks.0.remove(&event.client_id);
+
+        let grace = settings.disconnect_grace();
+        let mut kept_body = false;
+        for (entity, _) in player_query
+            .iter()
+            .filter(|(_, player_id)| player_id.0 == event.client_id)
+        {
+            if grace.is_zero() {
+                info!("Despawning the player of {:?}", event.client_id);
+                // the head is a child of the body
+                commands.entity(entity).despawn_recursive();
+            } else {
+                info!(
+                    "Keeping the player of {:?} for {:?}",
+                    event.client_id, grace
+                );
+                commands.entity(entity).insert(DisconnectedPlayer {
+                    despawn_at: time.elapsed() + grace,
+                });
+                kept_body = true;
+            }
+        }
+        // there is nothing left to resume
+        if !kept_body {
+            sessions
+                .0
+                .retain(|_, client_id| *client_id != event.client_id);
+        }
+    }
+}
+
+fn despawn_abandoned_players(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut sessions: ResMut<Sessions>,
+    player_query: Query<(Entity, &PlayerId, &DisconnectedPlayer)>,
+) {
+    for (entity, player_id, disconnected) in &player_query {
+        if time.elapsed() >= disconnected.despawn_at {
+            info!("Despawning abandoned player {:?}", entity);
+            commands.entity(entity).despawn_recursive();
+            sessions.0.retain(|_, client_id| *client_id != player_id.0);
+        }
+    }
+}
+
+/// Nobody sends inputs for a disconnected player anymore, so it is held in place
+/// until its client comes back, instead of repeating the last inputs forever.
+fn freeze_disconnected_players(
+    mut player_query: Query<
+        (&mut ActionState<PlayerActions>, &mut LinearVelocity),
+        With<DisconnectedPlayer>,
+    >,
+) {
+    for (mut action_state, mut linear_velocity) in &mut player_query {
+        *action_state = ActionState::default();
+        linear_velocity.0 = Vector::ZERO;
+    }
+}
+
+fn clear_connected_clients(
+    mut commands: Commands,
+    mut clients: ResMut<ConnectedClients>,
+    mut pending_kicks: ResMut<PendingKicks>,
+    player_query: Query<Entity, With<PhysicalPlayerBodyMarker>>,
+) {
+    for entity in &player_query {
+        commands.entity(entity).despawn_recursive();
+    }
+    clients.0.clear();
+    pending_kicks.0.clear();
+}
+
+/// How long a kicked client gets to receive why, before the server drops its connection.
+const KICK_DELAY: Duration = Duration::from_millis(500);
+
+/// The clients that were told why they are kicked, and when their connection gets dropped.
+#[derive(Resource, Default, Debug)]
+pub(super) struct PendingKicks(HashMap<ClientId, Duration>);
+
+impl PendingKicks {
+    /// Tells the client why it is kicked and disconnects it once the message had time to
+    /// arrive: disconnecting right away would drop the message with the connection.
+    pub(super) fn kick(
+        &mut self,
+        client_id: ClientId,
+        mut reason: ConnectionRejected,
+        connection_manager: &mut ServerConnectionManager,
+        time: &Time,
+    ) {
+        if self.0.contains_key(&client_id) {
+            return;
+        }
+        warn!("Kicking {:?}: {:?}", client_id, reason);
+        let _ = connection_manager.send_message::<Channel1, _>(client_id, &mut reason);
+        self.0.insert(client_id, time.elapsed() + KICK_DELAY);
+    }
+}
+
+fn disconnect_kicked_clients(
+    time: Res<Time>,
+    mut pending_kicks: ResMut<PendingKicks>,
+    mut connections: ResMut<ServerConnections>,
+) {
+    pending_kicks.0.retain(|&client_id, disconnect_at| {
+        if time.elapsed() < *disconnect_at {
+            return true;
+        }
+        info!("Disconnecting kicked client {:?}", client_id);
+        if let Err(err) = connections.disconnect(client_id) {
+            error!("Could not disconnect client {:?}: {:?}", client_id, err);
+        }
+        false
+    });
+}
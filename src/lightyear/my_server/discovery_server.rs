@@ -0,0 +1,68 @@
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+
+use bevy::{prelude::*, time::common_conditions::on_timer};
+use lightyear::prelude::server::NetworkingState as ServerNetworkingState;
+
+use crate::lightyear::{
+    discovery::{ServerBeacon, BEACON_INTERVAL, DISCOVERY_PORT},
+    my_shared::lobby::Lobby,
+    settings::NetSettings,
+};
+
+/// Announces the running server to clients on the LAN.
+pub struct MyServerDiscoveryPlugin;
+
+impl Plugin for MyServerDiscoveryPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(ServerNetworkingState::Started), start_beacon)
+            .add_systems(OnExit(ServerNetworkingState::Started), stop_beacon)
+            .add_systems(
+                Update,
+                broadcast_beacon
+                    .run_if(resource_exists::<BeaconSocket>.and_then(on_timer(BEACON_INTERVAL))),
+            );
+    }
+}
+
+#[derive(Resource)]
+struct BeaconSocket(UdpSocket);
+
+fn start_beacon(mut commands: Commands, settings: Res<NetSettings>) {
+    if !settings.lan_discovery {
+        return;
+    }
+
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).and_then(|socket| {
+        socket.set_broadcast(true)?;
+        socket.set_nonblocking(true)?;
+        Ok(socket)
+    });
+    match socket {
+        Ok(socket) => {
+            info!("Broadcasting on the LAN as {:?}", settings.server_name);
+            commands.insert_resource(BeaconSocket(socket));
+        }
+        Err(err) => error!("Could not open the LAN discovery socket: {}", err),
+    }
+}
+
+fn stop_beacon(mut commands: Commands) {
+    commands.remove_resource::<BeaconSocket>();
+}
+
+fn broadcast_beacon(
+    socket: Res<BeaconSocket>,
+    settings: Res<NetSettings>,
+    lobby: Option<Res<Lobby>>,
+) {
+    let beacon = ServerBeacon {
+        name: settings.server_name.clone(),
+        map: settings.map.clone(),
+        players: lobby.map_or(0, |lobby| lobby.players.len() as u16),
+        port: settings.port,
+    };
+    let broadcast_addr = SocketAddr::from((Ipv4Addr::BROADCAST, DISCOVERY_PORT));
+    if let Err(err) = socket.0.send_to(&beacon.encode(), broadcast_addr) {
+        debug!("Could not broadcast the LAN beacon: {}", err);
+    }
+}
@@ -1,32 +1,105 @@
-use bevy::prelude::*;
-use lightyear::prelude::{
-    is_host_server, InputChannel, InputMessage, MainSet, NetworkTarget, ServerConnectionManager,
-    ServerMessageEvent,
+use std::f32::consts::SQRT_2;
+
+use bevy::{prelude::*, utils::HashMap};
+use lightyear::{
+    inputs::leafwing::{action_diff::ActionDiff, input_buffer::InputBuffer},
+    prelude::{
+        server::{DisconnectEvent, NetworkingState as ServerNetworkingState},
+        ClientId, InputChannel, InputMessage, MainSet, NetworkTarget, ServerConnectionManager,
+        ServerMessageEvent, Tick, TickManager,
+    },
+};
+
+use super::connection_server::PendingKicks;
+use crate::lightyear::{
+    my_shared::{
+        lib::{ConnectionRejected, PlayerActions, PlayerId},
+        movement::MAX_LOOK_DELTA,
+    },
+    settings::NetSettings,
 };
 
-use crate::lightyear::my_shared::lib::PlayerActions;
+/// How far ahead of the server a client's inputs may be, well above what any real latency needs.
+const MAX_INPUT_TICKS_AHEAD: i16 = 128;
+
+/// How far behind the server a client's inputs may be before they are useless to anyone.
+const MAX_INPUT_TICKS_BEHIND: i16 = 64;
+
+/// The longest `Move` a real device sends: a keyboard diagonal. The movement clamps it
+/// to 1 either way, but only a forged input is any longer.
+const MAX_LEGIT_MOVE_LENGTH: f32 = SQRT_2 + 0.001;
 
 pub struct MyServerInputPlugin;
 
 impl Plugin for MyServerInputPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
-            PreUpdate,
-            replicate_inputs
-                .after(MainSet::EmitEvents)
-                .run_if(is_host_server),
-        );
+        app.init_resource::<RejectedInputs>()
+            .add_systems(
+                PreUpdate,
+                replicate_inputs
+                    .after(MainSet::EmitEvents)
+                    .run_if(in_state(ServerNetworkingState::Started)),
+            )
+            .add_systems(
+                Update,
+                (forget_disconnected_clients, kick_cheating_clients)
+                    .chain()
+                    .run_if(in_state(ServerNetworkingState::Started)),
+            )
+            .add_systems(
+                OnExit(ServerNetworkingState::Started),
+                clear_rejected_inputs,
+            );
     }
 }
 
+/// How many invalid inputs each client sent since it connected.
+#[derive(Resource, Default, Debug)]
+pub(super) struct RejectedInputs(pub(super) HashMap<ClientId, u32>);
+
+/// Rebroadcasts the inputs of each client to the others, unless they are for ticks too far
+/// from the server's. A message moving or looking around further than any real input can is
+/// counted once and clamped before the others see it.
+///
+/// By the time the message event is emitted lightyear already wrote the inputs into the
+/// player's [`InputBuffer`], so those of a rejected message beyond the window get dropped from
+/// the buffer again. Inputs for ticks we already simulated are never read, there is nothing to undo.
 fn replicate_inputs(
     mut connection: ResMut<ServerConnectionManager>,
     mut input_events: ResMut<Events<ServerMessageEvent<InputMessage<PlayerActions>>>>,
+    mut rejected: ResMut<RejectedInputs>,
+    mut buffer_query: Query<(&PlayerId, &mut InputBuffer<PlayerActions>)>,
+    tick_manager: Res<TickManager>,
 ) {
+    let tick = tick_manager.tick();
     for mut event in input_events.drain() {
         let client_id = *event.context();
 
-        // Optional: do some validation on the inputs to check that there's no cheating
+        let ticks_ahead = event.message.end_tick - tick;
+        if !(-MAX_INPUT_TICKS_BEHIND..=MAX_INPUT_TICKS_AHEAD).contains(&ticks_ahead) {
+            debug!(
+                "Rejecting inputs of {:?} for tick {:?}, {} ticks away from ours",
+                client_id, event.message.end_tick, ticks_ahead
+            );
+            *rejected.0.entry(client_id).or_default() += 1;
+            if ticks_ahead > MAX_INPUT_TICKS_AHEAD {
+                for (_, mut buffer) in buffer_query
+                    .iter_mut()
+                    .filter(|(player_id, _)| player_id.0 == client_id)
+                {
+                    truncate_input_buffer(&mut buffer, tick, tick + MAX_INPUT_TICKS_AHEAD);
+                }
+            }
+            continue;
+        }
+
+        if clamp_input_message(&mut event.message) {
+            debug!(
+                "Clamping forged inputs of {:?} for tick {:?}",
+                client_id, event.message.end_tick
+            );
+            *rejected.0.entry(client_id).or_default() += 1;
+        }
 
         // rebroadcast the input to other clients
         connection
@@ -37,3 +110,85 @@ fn replicate_inputs(
             .unwrap()
     }
 }
+
+/// Keeps only the buffered inputs from `first` to `last`, the ticks the server can still use.
+fn truncate_input_buffer(buffer: &mut InputBuffer<PlayerActions>, first: Tick, last: Tick) {
+    let mut kept = InputBuffer::default();
+    let mut tick = first;
+    while tick <= last {
+        if let Some(action_state) = buffer.get(tick) {
+            kept.set(tick, action_state.clone());
+        }
+        tick = tick + 1;
+    }
+    *buffer = kept;
+}
+
+/// Bounds `Move` and `LookAround` in every diff of the message to what a real device sends,
+/// returns whether any of them was out of bounds.
+///
+/// The movement clamps both on every peer too, the others' predictions just never see more.
+fn clamp_input_message(message: &mut InputMessage<PlayerActions>) -> bool {
+    let mut clamped = false;
+    for (_, diffs) in &mut message.diffs {
+        for diff in diffs.iter_mut().flatten() {
+            let ActionDiff::DualAxisChanged { action, axis_pair } = diff else {
+                continue;
+            };
+            let max_length = match action {
+                PlayerActions::Move => MAX_LEGIT_MOVE_LENGTH,
+                PlayerActions::LookAround => MAX_LOOK_DELTA,
+                _ => continue,
+            };
+            if !axis_pair.is_finite() {
+                *axis_pair = Vec2::ZERO;
+                clamped = true;
+            } else if axis_pair.length() > max_length {
+                *axis_pair = axis_pair.clamp_length_max(max_length);
+                clamped = true;
+            }
+        }
+    }
+    clamped
+}
+
+fn kick_cheating_clients(
+    settings: Res<NetSettings>,
+    time: Res<Time>,
+    mut rejected: ResMut<RejectedInputs>,
+    mut pending_kicks: ResMut<PendingKicks>,
+    mut connection_manager: ResMut<ServerConnectionManager>,
+) {
+    let Some(threshold) = settings.kick_after_invalid_inputs else {
+        return;
+    };
+    let cheaters: Vec<ClientId> = rejected
+        .0
+        .iter()
+        .filter(|(_, &count)| count >= threshold)
+        .map(|(&client_id, _)| client_id)
+        .collect();
+
+    for client_id in cheaters {
+        rejected.0.remove(&client_id);
+        pending_kicks.kick(
+            client_id,
+            ConnectionRejected::TooManyInvalidInputs,
+            &mut connection_manager,
+            &time,
+        );
+    }
+}
+
+fn forget_disconnected_clients(
+    mut events: EventReader<DisconnectEvent>,
+    mut rejected: ResMut<RejectedInputs>,
+) {
+    for event in events.read() {
+        rejected.0.remove(&event.client_id);
+    }
+}
+
+fn clear_rejected_inputs(mut rejected: ResMut<RejectedInputs>) {
+    rejected.0.clear();
+}
@@ -0,0 +1,135 @@
+use bevy::prelude::*;
+use lightyear::prelude::{
+    server::{ConnectEvent, DisconnectEvent, NetworkingState as ServerNetworkingState},
+    ClientId, NetworkTarget, ReplicateResourceExt, ServerMessageEvent, Tick, TickManager,
+};
+
+use crate::{
+    lightyear::{
+        my_shared::{
+            lib::Channel1,
+            lobby::{Lobby, LobbyPlayer, SetReady, StartMatch},
+        },
+        settings::NetSettings,
+    },
+    my_states::GameState,
+};
+
+/// How long after the host pressed start everyone enters the match,
+/// enough for the start tick to reach every client in time.
+const START_DELAY_TICKS: u16 = 64;
+
+/// Keeps the lobby up to date with who is connected and ready, and starts the match.
+pub struct MyServerLobbyPlugin;
+
+impl Plugin for MyServerLobbyPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(ServerNetworkingState::Started), open_lobby)
+            .add_systems(
+                Update,
+                (
+                    track_lobby_players,
+                    handle_ready,
+                    handle_start_match,
+                    mark_match_started.run_if(in_state(GameState::Lobby)),
+                )
+                    .chain()
+                    .run_if(in_state(ServerNetworkingState::Started)),
+            )
+            .add_systems(OnExit(ServerNetworkingState::Started), close_lobby);
+    }
+}
+
+fn open_lobby(mut commands: Commands, settings: Res<NetSettings>) {
+    commands.insert_resource(Lobby {
+        map: settings.map.clone(),
+        mode: settings.mode,
+        ..default()
+    });
+    commands.replicate_resource::<Lobby, Channel1>(NetworkTarget::All);
+}
+
+fn close_lobby(mut commands: Commands) {
+    commands.remove_resource::<Lobby>();
+}
+
+fn track_lobby_players(
+    mut connections: EventReader<ConnectEvent>,
+    mut disconnections: EventReader<DisconnectEvent>,
+    mut lobby: ResMut<Lobby>,
+) {
+    for event in connections.read() {
+        let client_id = event.client_id;
+        if lobby
+            .players
+            .iter()
+            .any(|player| player.client_id == client_id)
+        {
+            continue;
+        }
+        lobby.players.push(LobbyPlayer {
+            client_id,
+            ready: false,
+        });
+        // the host's own client always runs the lobby it hosts
+        if lobby.host.is_none() || matches!(client_id, ClientId::Local(_)) {
+            lobby.host = Some(client_id);
+        }
+    }
+
+    for event in disconnections.read() {
+        lobby
+            .players
+            .retain(|player| player.client_id != event.client_id);
+        if lobby.host == Some(event.client_id) {
+            lobby.host = lobby.players.first().map(|player| player.client_id);
+        }
+    }
+}
+
+fn handle_ready(mut events: EventReader<ServerMessageEvent<SetReady>>, mut lobby: ResMut<Lobby>) {
+    for event in events.read() {
+        let client_id = *event.context();
+        let SetReady(ready) = *event.message();
+        if let Some(player) = lobby
+            .players
+            .iter_mut()
+            .find(|player| player.client_id == client_id)
+        {
+            player.ready = ready;
+        }
+    }
+}
+
+fn handle_start_match(
+    mut events: EventReader<ServerMessageEvent<StartMatch>>,
+    mut lobby: ResMut<Lobby>,
+    tick_manager: Res<TickManager>,
+) {
+    for event in events.read() {
+        let client_id = *event.context();
+        if lobby.host != Some(client_id) {
+            warn!(
+                "Client {:?} tried to start the match without being the host",
+                client_id
+            );
+            continue;
+        }
+        if lobby.start_tick.is_some() || !lobby.everyone_ready() {
+            continue;
+        }
+
+        let start_tick = Tick(tick_manager.tick().0.wrapping_add(START_DELAY_TICKS));
+        info!("Starting the match on tick {:?}", start_tick);
+        lobby.start_tick = Some(start_tick);
+    }
+}
+
+fn mark_match_started(mut lobby: ResMut<Lobby>, tick_manager: Res<TickManager>) {
+    if lobby
+        .start_tick
+        .is_some_and(|start_tick| tick_manager.tick() >= start_tick)
+    {
+        lobby.started = true;
+    }
+}
@@ -1,44 +1,104 @@
+use auth_server::MyServerAuthPlugin;
 use bevy::prelude::*;
+use chat_server::MyServerChatPlugin;
+use checksum_server::MyServerChecksumPlugin;
+use connection_server::MyServerConnectionPlugin;
+use discovery_server::MyServerDiscoveryPlugin;
 use input_server::MyServerInputPlugin;
 use lightyear::prelude::*;
+use lobby_server::MyServerLobbyPlugin;
 use movement_server::MyServerMovementPlugin;
+use net_stats_server::MyServerNetStatsPlugin;
 use server::{
-    ControlledBy, IoConfig, NetConfig, NetcodeConfig, Replicate, ServerConfig, ServerPlugins,
-    ServerTransport, SyncTarget,
+    ControlledBy, Lifetime, NetworkingState as ServerNetworkingState, Replicate, ServerConfig,
+    ServerPlugins, SyncTarget,
 };
+use session_server::MyServerSessionPlugin;
+use spawn_server::{MyServerSpawnPlugin, PendingRespawn};
 
 use super::{
-    lib::SERVER_ADDR,
+    conditioner::LinkConditioners,
+    lib::server_net_config,
     my_shared::{
-        lib::{PhysicalPlayerBodyMarker, PLAYER_REPLICATION_GROUP, SERVER_REPLICATION_INTERVAL},
+        lib::{
+            PhysicalPlayerBodyMarker, PhysicalPlayerHeadBundle, PhysicalPlayerServerBodyBundle,
+            PLAYER_REPLICATION_GROUP, SERVER_REPLICATION_INTERVAL,
+        },
         shared_config,
     },
+    settings::NetSettings,
 };
 
+mod auth_server;
+mod chat_server;
+mod checksum_server;
+mod connection_server;
+mod discovery_server;
 mod input_server;
+mod lobby_server;
 mod movement_server;
+mod net_stats_server;
+mod session_server;
+mod spawn_server;
 
-pub struct MyServerPlugin;
+pub struct MyServerPlugin {
+    /// `Mode::HostServer` when the server runs inside a client app,
+    /// `Mode::Separate` for the headless dedicated server.
+    pub mode: Mode,
+}
 
 impl Plugin for MyServerPlugin {
     fn build(&self, app: &mut App) {
+        let settings = app.world().resource::<NetSettings>().clone();
+        let conditioners = *app.world().resource::<LinkConditioners>();
+
         app.add_plugins((
-            build_server_plugin(),
+            build_server_plugin(self.mode, &settings, &conditioners),
             MyServerMovementPlugin,
             MyServerInputPlugin,
+            MyServerConnectionPlugin,
+            MyServerAuthPlugin,
+            MyServerChecksumPlugin,
+            MyServerSpawnPlugin,
+            MyServerSessionPlugin,
+            MyServerLobbyPlugin,
+            MyServerDiscoveryPlugin,
+            MyServerChatPlugin,
+            MyServerNetStatsPlugin,
         ))
-        .add_systems(Update, replicate_players.run_if(is_host_server));
+        .add_systems(
+            Update,
+            replicate_players.run_if(in_state(ServerNetworkingState::Started)),
+        );
     }
 }
 
 // Replicate the pre-predicted entities back to the client(s)
 fn replicate_players(
     mut commands: Commands,
-    query_body: Query<(Entity, &Replicated), (Added<Replicated>, With<PhysicalPlayerBodyMarker>)>,
+    query_body: Query<
+        (Entity, &Replicated, Has<PhysicalPlayerBodyMarker>),
+        (Added<Replicated>, With<PrePredicted>),
+    >,
 ) {
-    for (entity, replicated) in query_body.iter() {
+    for (entity, replicated, has_body) in query_body.iter() {
         let client_id = replicated.client_id();
 
+        // the body was spawned in another world (i.e. not by the host's client),
+        // so only the pre-predicted entity made it over: build the rest of the player here
+        if !has_body {
+            info!(
+                "Completing remote player body: {:?} / client: {:?}",
+                entity, client_id
+            );
+            commands
+                .entity(entity)
+                .insert(PhysicalPlayerServerBodyBundle::new(client_id))
+                .with_children(|commands| {
+                    commands.spawn(PhysicalPlayerHeadBundle::new(client_id));
+                });
+        }
+
         // for all player entities we have received, add a Replicate component so that we can start replicating it
         // to other clients
         if let Some(mut e) = commands.get_entity(entity) {
@@ -50,7 +110,8 @@ fn replicate_players(
                 sync: sync_target,
                 controlled_by: ControlledBy {
                     target: NetworkTarget::Single(client_id),
-                    ..default()
+                    // we decide ourselves what happens to the body when the client leaves
+                    lifetime: Lifetime::Persistent,
                 },
                 // make sure that all entities that are predicted are part of the same replication group
                 group: PLAYER_REPLICATION_GROUP,
@@ -61,25 +122,26 @@ fn replicate_players(
                 // if we receive a pre-predicted entity, only send the prepredicted component back
                 // to the original client
                 OverrideTargetComponent::<PrePredicted>::new(NetworkTarget::Single(client_id)),
-                // not all physics components are replicated over the network, so add them on the server as well
-                //PhysicsBundle::player(), // we always run in host server, the client will insert this himself
+                // wherever the client put its body, the server decides where it spawns
+                PendingRespawn,
+                // not all physics components are replicated over the network: in host-server mode the
+                // client inserted them itself, otherwise they come with `PhysicalPlayerServerBodyBundle`
             ));
         }
     }
 }
 
-fn build_server_plugin() -> ServerPlugins {
-    let io = IoConfig {
-        transport: ServerTransport::UdpSocket(SERVER_ADDR),
-        ..default()
-    };
-    let net_config = NetConfig::Netcode {
-        io,
-        config: NetcodeConfig::default(),
-    };
+fn build_server_plugin(
+    mode: Mode,
+    settings: &NetSettings,
+    conditioners: &LinkConditioners,
+) -> ServerPlugins {
     let config = ServerConfig {
-        shared: shared_config(),
-        net: vec![net_config],
+        shared: shared_config(mode),
+        // a server the settings can't run never starts, see `NetSettings::check_server`
+        net: server_net_config(settings, conditioners)
+            .into_iter()
+            .collect(),
         replication: ReplicationConfig {
             send_interval: SERVER_REPLICATION_INTERVAL,
             ..default()
@@ -1,12 +1,10 @@
-use avian3d::prelude::*;
 use bevy::prelude::*;
 use leafwing_input_manager::prelude::ActionState;
-use lightyear::prelude::is_host_server;
+use lightyear::prelude::server::NetworkingState as ServerNetworkingState;
 
 use crate::lightyear::my_shared::{
     lib::{FixedSet, PhysicalPlayerBodyMarker, PhysicalPlayerHeadMarker, PlayerActions, PlayerId},
-    movement::shared_movement,
-    physics::{Grounded, JumpImpulse, MaxMovementSpeed, MovementAcceleration},
+    movement::{shared_movement, CharacterQuery},
 };
 
 pub struct MyServerMovementPlugin;
@@ -16,7 +14,7 @@ impl Plugin for MyServerMovementPlugin {
         app.add_systems(
             FixedUpdate,
             movement_server
-                .run_if(is_host_server)
+                .run_if(in_state(ServerNetworkingState::Started))
                 .in_set(FixedSet::Main),
         );
     }
@@ -28,34 +26,11 @@ fn movement_server(
         (Without<PhysicalPlayerBodyMarker>,),
     >,
     mut player_body_controllers: Query<
-        (
-            &Transform,
-            &MovementAcceleration,
-            &MaxMovementSpeed,
-            &JumpImpulse,
-            &mut LinearVelocity,
-            &mut Rotation,
-            &mut PhysicalPlayerBodyMarker,
-            &ActionState<PlayerActions>,
-            Has<Grounded>,
-            &Children,
-        ),
+        (CharacterQuery, &ActionState<PlayerActions>, &Children),
         (With<PlayerId>, Without<PhysicalPlayerHeadMarker>),
     >,
 ) {
-    for (
-        transform,
-        movement_acceleration,
-        max_speed,
-        jump_impulse,
-        mut body_velocity,
-        mut body_rotation,
-        mut player_body,
-        action_state,
-        is_grounded,
-        children,
-    ) in &mut player_body_controllers
-    {
+    for (mut character, action_state, children) in &mut player_body_controllers {
         let (head_entity, _, _) = children
             .iter()
             .map(|entity| player_head_query.get(*entity).ok())
@@ -64,18 +39,6 @@ fn movement_server(
             .unwrap();
         let (_, mut head_transform, mut head) = player_head_query.get_mut(head_entity).unwrap();
 
-        shared_movement(
-            transform,
-            jump_impulse,
-            movement_acceleration,
-            max_speed,
-            &mut body_velocity,
-            &mut body_rotation,
-            &mut player_body,
-            &mut head_transform,
-            &mut head,
-            is_grounded,
-            action_state,
-        );
+        shared_movement(&mut character, &mut head_transform, &mut head, action_state);
     }
 }
@@ -0,0 +1,73 @@
+use bevy::{prelude::*, time::common_conditions::on_timer};
+use lightyear::prelude::{
+    server::NetworkingState as ServerNetworkingState, NetworkTarget, ServerConnectionManager,
+    TickManager,
+};
+
+use super::{connection_server::ConnectedClients, input_server::RejectedInputs};
+use crate::lightyear::net_stats::{
+    ClientNetStats, Heartbeat, HeartbeatChannel, ServerNetStats, SAMPLE_INTERVAL,
+};
+
+/// Samples what the server knows about each client's connection, for the host's network HUD.
+pub struct MyServerNetStatsPlugin;
+
+impl Plugin for MyServerNetStatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ServerNetStats>()
+            .add_systems(
+                FixedUpdate,
+                send_heartbeats.run_if(in_state(ServerNetworkingState::Started)),
+            )
+            .add_systems(
+                Update,
+                sample_client_stats.run_if(
+                    in_state(ServerNetworkingState::Started).and_then(on_timer(SAMPLE_INTERVAL)),
+                ),
+            )
+            .add_systems(OnExit(ServerNetworkingState::Started), clear_client_stats);
+    }
+}
+
+/// Lets the clients measure their packet loss, whatever else is sent to them.
+fn send_heartbeats(
+    tick_manager: Res<TickManager>,
+    mut connection: ResMut<ServerConnectionManager>,
+) {
+    let _ = connection.send_message_to_target::<HeartbeatChannel, _>(
+        &mut Heartbeat {
+            tick: tick_manager.tick(),
+        },
+        NetworkTarget::All,
+    );
+}
+
+fn sample_client_stats(
+    connection_manager: Res<ServerConnectionManager>,
+    clients: Res<ConnectedClients>,
+    rejected_inputs: Res<RejectedInputs>,
+    mut stats: ResMut<ServerNetStats>,
+) {
+    stats.0 = clients
+        .0
+        .iter()
+        .filter_map(|&client_id| {
+            let connection = connection_manager.connection(client_id).ok()?;
+            Some(ClientNetStats {
+                client_id,
+                rtt: connection.rtt(),
+                jitter: connection.jitter(),
+                rejected_inputs: rejected_inputs
+                    .0
+                    .get(&client_id)
+                    .copied()
+                    .unwrap_or_default(),
+            })
+        })
+        .collect();
+    stats.0.sort_by_key(|client| client.client_id.to_bits());
+}
+
+fn clear_client_stats(mut stats: ResMut<ServerNetStats>) {
+    stats.0.clear();
+}
@@ -0,0 +1,107 @@
+use bevy::{prelude::*, utils::HashMap};
+use lightyear::prelude::{
+    server::{ControlledBy, Lifetime, NetworkingState as ServerNetworkingState},
+    ClientId, NetworkTarget, OverrideTargetComponent, PrePredicted, ServerConnectionManager,
+    ServerMessageEvent,
+};
+
+use super::connection_server::DisconnectedPlayer;
+use crate::lightyear::my_shared::lib::{
+    Channel1, JoinRequest, PhysicalPlayerBodyMarker, PhysicalPlayerHeadMarker, PlayerId,
+    SessionAccepted,
+};
+
+/// Hands out session tokens and gives reconnecting clients their old player back.
+pub struct MyServerSessionPlugin;
+
+impl Plugin for MyServerSessionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Sessions>()
+            .add_systems(
+                Update,
+                handle_join_requests.run_if(in_state(ServerNetworkingState::Started)),
+            )
+            .add_systems(OnExit(ServerNetworkingState::Started), clear_sessions);
+    }
+}
+
+/// The client currently owning each session token.
+#[derive(Resource, Default, Debug)]
+pub(super) struct Sessions(pub(super) HashMap<u64, ClientId>);
+
+fn handle_join_requests(
+    mut commands: Commands,
+    mut events: EventReader<ServerMessageEvent<JoinRequest>>,
+    mut sessions: ResMut<Sessions>,
+    mut connection_manager: ResMut<ServerConnectionManager>,
+    body_query: Query<(Entity, &PlayerId, &Children), With<DisconnectedPlayer>>,
+    head_query: Query<(), With<PhysicalPlayerHeadMarker>>,
+) {
+    for event in events.read() {
+        let client_id = *event.context();
+
+        // only a player still waiting in its grace period can be taken over
+        let resumed = event.message().resume_token.and_then(|token| {
+            let previous_client = *sessions.0.get(&token)?;
+            let (body, _, children) = body_query
+                .iter()
+                .find(|(_, player_id, _)| player_id.0 == previous_client)?;
+            Some((token, body, children))
+        });
+
+        let (token, resumed) = match resumed {
+            Some((token, body, children)) => {
+                info!("Client {:?} resumed session {}", client_id, token);
+                rebind_player(&mut commands, body, children, &head_query, client_id);
+                (token, true)
+            }
+            None => (rand::random(), false),
+        };
+        sessions.0.insert(token, client_id);
+
+        let _ = connection_manager
+            .send_message::<Channel1, _>(client_id, &mut SessionAccepted { token, resumed });
+    }
+}
+
+/// Hands the body and head of a disconnected player over to the client that resumed its session.
+///
+/// The new client never spawned this body, so it gets replicated to it as a regular predicted
+/// entity instead of a pre-predicted one.
+fn rebind_player(
+    commands: &mut Commands,
+    body: Entity,
+    children: &Children,
+    head_query: &Query<(), With<PhysicalPlayerHeadMarker>>,
+    client_id: ClientId,
+) {
+    commands
+        .entity(body)
+        .insert((
+            PlayerId(client_id),
+            Name::new(format!("PhysicalPlayerBody-{}", client_id)),
+            ControlledBy {
+                target: NetworkTarget::Single(client_id),
+                lifetime: Lifetime::Persistent,
+            },
+        ))
+        .remove::<(
+            DisconnectedPlayer,
+            PrePredicted,
+            OverrideTargetComponent<PrePredicted>,
+        )>();
+
+    for head in children
+        .iter()
+        .filter(|entity| head_query.contains(**entity))
+    {
+        commands.entity(*head).insert((
+            PlayerId(client_id),
+            Name::new(format!("PhysicalPlayerHead-{}", client_id)),
+        ));
+    }
+}
+
+fn clear_sessions(mut sessions: ResMut<Sessions>) {
+    sessions.0.clear();
+}
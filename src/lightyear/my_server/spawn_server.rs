@@ -0,0 +1,184 @@
+use avian3d::{
+    math::{Quaternion, Scalar, Vector},
+    prelude::*,
+};
+use bevy::{ecs::query::QueryData, prelude::*};
+use lightyear::prelude::{server::NetworkingState as ServerNetworkingState, ServerMessageEvent};
+
+use crate::{
+    lightyear::{
+        my_shared::{
+            lib::{FixedSet, PhysicalPlayerBodyMarker, PlayerId, Respawn, TeleportCount},
+            lobby::{GameMode, Lobby},
+            physics::{GroundVelocity, JumpTimers, MovementState},
+        },
+        settings::NetSettings,
+    },
+    map::{KillVolume, MapBounds, SpawnPoint, SpawnPolicy},
+};
+
+pub struct MyServerSpawnPlugin;
+
+impl Plugin for MyServerSpawnPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NextSpawnPoint>()
+            .add_systems(
+                Update,
+                handle_respawn_requests.run_if(in_state(ServerNetworkingState::Started)),
+            )
+            .add_systems(
+                FixedUpdate,
+                (kill_out_of_bounds_players, respawn_players)
+                    .chain()
+                    .run_if(in_state(ServerNetworkingState::Started))
+                    .in_set(FixedSet::Main),
+            );
+    }
+}
+
+/// A body that gets moved to a spawn point on the next tick.
+#[derive(Component, Debug)]
+pub(super) struct PendingRespawn;
+
+/// A body that gets moved to the given position on the next tick.
+#[derive(Component, Debug)]
+struct PendingTeleport(Vector);
+
+/// The round robin counter of [`SpawnPolicy::RoundRobin`](crate::map::SpawnPolicy::RoundRobin).
+#[derive(Resource, Default, Debug)]
+struct NextSpawnPoint(usize);
+
+#[derive(QueryData)]
+#[query_data(mutable)]
+struct RespawnQuery {
+    entity: Entity,
+    position: &'static mut Position,
+    rotation: &'static mut Rotation,
+    linear_velocity: &'static mut LinearVelocity,
+    body: &'static mut PhysicalPlayerBodyMarker,
+    movement_state: &'static mut MovementState,
+    jump_timers: &'static mut JumpTimers,
+    ground_velocity: &'static mut GroundVelocity,
+    teleport_count: &'static mut TeleportCount,
+    respawn: Has<PendingRespawn>,
+    teleport: Option<&'static PendingTeleport>,
+}
+
+fn handle_respawn_requests(
+    mut commands: Commands,
+    mut events: EventReader<ServerMessageEvent<Respawn>>,
+    player_query: Query<(Entity, &PlayerId), With<PhysicalPlayerBodyMarker>>,
+) {
+    for event in events.read() {
+        let client_id = *event.context();
+        for (entity, _) in player_query
+            .iter()
+            .filter(|(_, player_id)| player_id.0 == client_id)
+        {
+            info!("Client {:?} asked to respawn", client_id);
+            commands.entity(entity).insert(PendingRespawn);
+        }
+    }
+}
+
+/// Respawns the bodies that left the map bounds, and the ones touching a kill volume
+/// unless it teleports them somewhere instead.
+fn kill_out_of_bounds_players(
+    mut commands: Commands,
+    spatial_query: SpatialQuery,
+    bounds_query: Query<&MapBounds>,
+    kill_volume_query: Query<(&Collider, &Position, &Rotation, &KillVolume)>,
+    player_query: Query<
+        (Entity, &Position),
+        (
+            With<PhysicalPlayerBodyMarker>,
+            Without<PendingRespawn>,
+            Without<PendingTeleport>,
+        ),
+    >,
+) {
+    let bounds = bounds_query.get_single().copied().unwrap_or_default();
+    for (entity, position) in &player_query {
+        if !bounds.contains(position.0) {
+            info!("{:?} left the map at {}", entity, position.0);
+            commands.entity(entity).insert(PendingRespawn);
+        }
+    }
+
+    for (collider, position, rotation, kill_volume) in &kill_volume_query {
+        for entity in spatial_query.shape_intersections(
+            collider,
+            position.0,
+            rotation.0,
+            SpatialQueryFilter::default(),
+        ) {
+            if !player_query.contains(entity) {
+                continue;
+            }
+            info!("{:?} entered a kill volume", entity);
+            match kill_volume.teleport_to {
+                Some(destination) => commands.entity(entity).insert(PendingTeleport(destination)),
+                None => commands.entity(entity).insert(PendingRespawn),
+            };
+        }
+    }
+}
+
+/// Moves new, killed and respawning bodies to a spawn point, or teleporting ones to their
+/// destination, and resets their movement.
+fn respawn_players(
+    mut commands: Commands,
+    settings: Res<NetSettings>,
+    lobby: Option<Res<Lobby>>,
+    mut next_spawn_point: ResMut<NextSpawnPoint>,
+    spawn_point_query: Query<(&Transform, &SpawnPoint)>,
+    mut player_query: Query<RespawnQuery>,
+) {
+    let spawn_points: Vec<(Vector, Scalar)> = spawn_point_query
+        .iter()
+        .map(|(transform, spawn_point)| (transform.translation, spawn_point.yaw))
+        .collect();
+    // deathmatch keeps players apart whatever the server was configured with
+    let policy = match lobby.map(|lobby| lobby.mode) {
+        Some(GameMode::Deathmatch) => SpawnPolicy::Farthest,
+        _ => settings.spawn_policy,
+    };
+    let players: Vec<(Entity, Vector)> = player_query
+        .iter()
+        .map(|player| (player.entity, player.position.0))
+        .collect();
+
+    for mut player in &mut player_query {
+        if !player.respawn {
+            if let Some(&PendingTeleport(destination)) = player.teleport {
+                info!("Teleporting {:?} to {}", player.entity, destination);
+                player.position.0 = destination;
+                player.linear_velocity.0 = Vector::ZERO;
+                player.ground_velocity.0 = Vector::ZERO;
+                player.teleport_count.0 = player.teleport_count.0.wrapping_add(1);
+                commands.entity(player.entity).remove::<PendingTeleport>();
+            }
+            continue;
+        }
+
+        let enemies: Vec<Vector> = players
+            .iter()
+            .filter(|(entity, _)| *entity != player.entity)
+            .map(|(_, position)| *position)
+            .collect();
+        let (position, yaw) = policy.pick(&spawn_points, &enemies, &mut next_spawn_point.0);
+        info!("Spawning {:?} at {}", player.entity, position);
+
+        player.position.0 = position;
+        player.rotation.0 = Quaternion::from_rotation_y(yaw);
+        player.linear_velocity.0 = Vector::ZERO;
+        player.body.yaw = yaw;
+        *player.movement_state = MovementState::default();
+        *player.jump_timers = JumpTimers::default();
+        player.ground_velocity.0 = Vector::ZERO;
+        player.teleport_count.0 = player.teleport_count.0.wrapping_add(1);
+        commands
+            .entity(player.entity)
+            .remove::<(PendingRespawn, PendingTeleport)>();
+    }
+}
@@ -0,0 +1,57 @@
+use std::{collections::VecDeque, time::Duration};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::lib::PlayerId;
+
+/// The longest chat message the server passes on, in characters.
+pub const MAX_CHAT_LENGTH: usize = 200;
+
+/// How many received lines the client keeps around.
+const CHAT_HISTORY: usize = 50;
+
+/// Sent by a client to say something to everyone.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChatMessage(pub String);
+
+/// A chat message the server accepted, passed on to every client.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChatBroadcast {
+    pub sender: PlayerId,
+    pub text: String,
+}
+
+/// The chat lines received since entering the game, oldest first.
+#[derive(Resource, Default, Debug)]
+pub struct ChatLog(pub VecDeque<ChatLine>);
+
+#[derive(Debug, Clone)]
+pub struct ChatLine {
+    pub sender: PlayerId,
+    pub text: String,
+    pub received: Duration,
+}
+
+impl ChatLog {
+    pub fn push(&mut self, line: ChatLine) {
+        self.0.push_back(line);
+        if self.0.len() > CHAT_HISTORY {
+            self.0.pop_front();
+        }
+    }
+}
+
+/// The message being typed, `open` while the chat box has the keyboard.
+#[derive(Resource, Default, Debug)]
+pub struct ChatInput {
+    pub open: bool,
+    pub draft: String,
+}
+
+/// What the server accepts as a chat message: trimmed, without control characters,
+/// neither empty nor longer than [`MAX_CHAT_LENGTH`].
+pub fn sanitize_chat_message(text: &str) -> Option<String> {
+    let text: String = text.trim().chars().filter(|c| !c.is_control()).collect();
+    (!text.is_empty() && text.chars().count() <= MAX_CHAT_LENGTH).then_some(text)
+}
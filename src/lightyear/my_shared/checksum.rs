@@ -0,0 +1,82 @@
+use avian3d::prelude::*;
+use lightyear::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::lib::{PhysicalPlayerBodyMarker, PhysicalPlayerHeadMarker};
+
+/// Carries the server's [`ChecksumMessage`]s, separate from [`Channel1`](super::lib::Channel1)
+/// so that losing one only costs a single tick of checks.
+#[derive(Channel)]
+pub struct ChecksumChannel;
+
+/// The checksums of every player's physics state after the server simulated `tick`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChecksumMessage {
+    pub tick: Tick,
+    pub players: Vec<(ClientId, PlayerChecksum)>,
+}
+
+/// One hash per replicated component of a player, so a mismatch tells us which one diverged.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlayerChecksum {
+    pub position: u64,
+    pub rotation: u64,
+    pub linear_velocity: u64,
+    pub body: u64,
+    /// `None` when the head entity isn't there (yet).
+    pub head: Option<u64>,
+}
+
+impl PlayerChecksum {
+    pub fn new(
+        position: &Position,
+        rotation: &Rotation,
+        linear_velocity: &LinearVelocity,
+        body: &PhysicalPlayerBodyMarker,
+        head: Option<&PhysicalPlayerHeadMarker>,
+    ) -> Self {
+        Self {
+            position: hash_floats(&position.to_array()),
+            rotation: hash_floats(&rotation.to_array()),
+            linear_velocity: hash_floats(&linear_velocity.to_array()),
+            body: hash_floats(&[body.yaw]),
+            head: head.map(|head| hash_floats(&[head.pitch])),
+        }
+    }
+
+    /// The names of the components whose checksum differs.
+    pub fn diff(&self, other: &Self) -> Vec<&'static str> {
+        let mut diverged = Vec::new();
+        if self.position != other.position {
+            diverged.push("Position");
+        }
+        if self.rotation != other.rotation {
+            diverged.push("Rotation");
+        }
+        if self.linear_velocity != other.linear_velocity {
+            diverged.push("LinearVelocity");
+        }
+        if self.body != other.body {
+            diverged.push("PhysicalPlayerBodyMarker");
+        }
+        if let (Some(head), Some(other_head)) = (self.head, other.head) {
+            if head != other_head {
+                diverged.push("PhysicalPlayerHeadMarker");
+            }
+        }
+        diverged
+    }
+}
+
+/// FNV-1a over the exact bits of the floats, so that it is stable across machines and builds.
+fn hash_floats(values: &[f32]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    values
+        .iter()
+        .flat_map(|value| value.to_bits().to_le_bytes())
+        .fold(OFFSET_BASIS, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(PRIME)
+        })
+}
@@ -15,7 +15,9 @@ use bevy::{ecs::entity::MapEntities, prelude::*};
 use serde::{Deserialize, Serialize};
 
 use super::physics::{
-    JumpImpulse, MaxMovementSpeed, MaxSlopeAngle, MovementAcceleration, MovementDampingFactor,
+    AirAcceleration, AirDampingFactor, AirMaxMovementSpeed, ControllerGravity, GroundVelocity,
+    JumpImpulse, JumpTimers, JumpWindows, MaxMovementSpeed, MaxSlopeAngle, MovementAcceleration,
+    MovementDampingFactor, MovementState,
 };
 
 pub const SERVER_REPLICATION_INTERVAL: Duration = Duration::from_millis(100);
@@ -33,6 +35,36 @@ pub enum FixedSet {
 #[derive(Channel)]
 pub struct Channel1;
 
+/// Sent by the server shortly before it drops a connection it refuses.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum ConnectionRejected {
+    /// The server's `--kick-after-invalid-inputs` threshold was reached.
+    TooManyInvalidInputs,
+}
+
+/// Sent by a client once connected, with the token of the session it wants to resume, if any.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JoinRequest {
+    pub resume_token: Option<u64>,
+}
+
+/// The server's answer to a [`JoinRequest`].
+///
+/// A resumed session gets its old player back, otherwise the client spawns a new one.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SessionAccepted {
+    pub token: u64,
+    pub resumed: bool,
+}
+
+/// Asks the server to respawn our player.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Respawn;
+
+/// Tells a client which map the server plays on, by its name in `assets/maps`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MapSelection(pub String);
+
 #[derive(Component, Reflect, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Deref)]
 #[reflect(Component)]
 pub struct PlayerId(pub ClientId);
@@ -90,6 +122,14 @@ impl Add for PhysicalPlayerBodyMarker {
     }
 }
 
+/// Counts how often the server teleported a body.
+///
+/// A client seeing it change knows the jump in position is not a misprediction, and snaps to the
+/// new position instead of smoothing the correction over several frames.
+#[derive(Component, Reflect, Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq)]
+#[reflect(Component)]
+pub struct TeleportCount(pub u16);
+
 #[derive(
     Component, Reflect, Default, Serialize, Deserialize, PartialEq, Debug, Clone, Deref, DerefMut,
 )]
@@ -142,6 +182,10 @@ impl PhysicalPlayerHeadBundle {
 pub(crate) struct PhysicalPlayerBodyBundle {
     name: Name,
     player_marker: PhysicalPlayerBodyMarker,
+    movement_state: MovementState,
+    jump_timers: JumpTimers,
+    ground_velocity: GroundVelocity,
+    teleport_count: TeleportCount,
     physics: PhysicsBundle,
     ground_caster: ShapeCaster,
     inputs: InputManagerBundle<PlayerActions>,
@@ -156,21 +200,16 @@ impl PhysicalPlayerBodyBundle {
         collider: Collider,
         player_id: ClientId,
     ) -> Self {
-        let mut caster_shape = collider.clone();
-        caster_shape.set_scale(Vector::ONE * 0.99, 10);
-
         Self {
             name: Name::new(format!("PhysicalPlayerBody-{}", player_id)),
             player_id: PlayerId(player_id),
             player_marker: PhysicalPlayerBodyMarker::default(),
+            movement_state: MovementState::default(),
+            jump_timers: JumpTimers::default(),
+            ground_velocity: GroundVelocity::default(),
+            teleport_count: TeleportCount::default(),
             physics: PhysicsBundle::player(),
-            ground_caster: ShapeCaster::new(
-                caster_shape,
-                Vector::ZERO,
-                Quaternion::default(),
-                Dir3::NEG_Y,
-            )
-            .with_max_time_of_impact(0.2),
+            ground_caster: ground_caster(collider),
             inputs: InputManagerBundle::<PlayerActions> {
                 action_state: ActionState::default(),
                 input_map,
@@ -184,22 +223,81 @@ impl PhysicalPlayerBodyBundle {
     }
 }
 
+/// The body of a player that was spawned by a remote client, as completed by a dedicated server.
+///
+/// In host-server mode the server shares its world with the client that spawned the body, but a
+/// dedicated server only receives the pre-predicted entity and has to add everything else itself.
+#[derive(Bundle)]
+pub(crate) struct PhysicalPlayerServerBodyBundle {
+    name: Name,
+    player_marker: PhysicalPlayerBodyMarker,
+    movement_state: MovementState,
+    jump_timers: JumpTimers,
+    ground_velocity: GroundVelocity,
+    teleport_count: TeleportCount,
+    physics: PhysicsBundle,
+    ground_caster: ShapeCaster,
+    action_state: ActionState<PlayerActions>,
+    player_id: PlayerId,
+    spatial: SpatialBundle,
+}
+
+impl PhysicalPlayerServerBodyBundle {
+    pub(crate) fn new(player_id: ClientId) -> Self {
+        let physics = PhysicsBundle::player();
+
+        Self {
+            name: Name::new(format!("PhysicalPlayerBody-{}", player_id)),
+            player_id: PlayerId(player_id),
+            player_marker: PhysicalPlayerBodyMarker::default(),
+            movement_state: MovementState::default(),
+            jump_timers: JumpTimers::default(),
+            ground_velocity: GroundVelocity::default(),
+            teleport_count: TeleportCount::default(),
+            ground_caster: ground_caster(physics.collider.clone()),
+            physics,
+            action_state: ActionState::default(),
+            spatial: SpatialBundle::from_transform(Transform::from_xyz(0.0, 5.0, 0.0)),
+        }
+    }
+}
+
+/// A shape caster slightly smaller than the collider, used to detect the ground.
+pub(crate) fn ground_caster(collider: Collider) -> ShapeCaster {
+    let mut caster_shape = collider;
+    caster_shape.set_scale(Vector::ONE * 0.99, 10);
+
+    ShapeCaster::new(
+        caster_shape,
+        Vector::ZERO,
+        Quaternion::default(),
+        Dir3::NEG_Y,
+    )
+    .with_max_time_of_impact(0.2)
+}
+
 #[derive(Bundle)]
 pub(crate) struct PhysicsBundle {
     pub(crate) collider: Collider,
     pub(crate) collider_density: ColliderDensity,
     pub(crate) rigid_body: RigidBody,
     pub(crate) locked_axes: LockedAxes,
+    /// Gravity comes from [`ControllerGravity`] instead.
+    pub(crate) gravity_scale: GravityScale,
+    /// Moving platforms carry players themselves, friction would drag them along a second time.
+    pub(crate) friction: Friction,
     pub(crate) movement: CharacterMovementBundle,
 }
 
 impl PhysicsBundle {
     pub(crate) fn player() -> Self {
         Self {
-            collider: Collider::capsule(0.4, 1.0),
+            collider: MovementState::default().collider(),
             collider_density: ColliderDensity(1.0),
             rigid_body: RigidBody::Dynamic,
             locked_axes: LockedAxes::ROTATION_LOCKED,
+            gravity_scale: GravityScale(0.0),
+            friction: Friction::ZERO.with_combine_rule(CoefficientCombine::Min),
             movement: CharacterMovementBundle::default(),
         }
     }
@@ -210,11 +308,19 @@ pub(crate) struct CharacterMovementBundle {
     acceleration: MovementAcceleration,
     max_speed: MaxMovementSpeed,
     damping: MovementDampingFactor,
+    air_acceleration: AirAcceleration,
+    air_max_speed: AirMaxMovementSpeed,
+    air_damping: AirDampingFactor,
     jump_impulse: JumpImpulse,
     max_slope_angle: MaxSlopeAngle,
+    gravity: ControllerGravity,
+    jump_windows: JumpWindows,
 }
 
 impl CharacterMovementBundle {
+    /// Uses the same acceleration, max speed and damping in the air as on the ground,
+    /// see [`Self::with_air_control`]. Falls with earth gravity and has 6 ticks of coyote time
+    /// and jump buffer, see [`Self::with_gravity`] and [`Self::with_jump_windows`].
     pub(crate) fn new(
         acceleration: Scalar,
         max_speed: Scalar,
@@ -226,15 +332,52 @@ impl CharacterMovementBundle {
             acceleration: MovementAcceleration(acceleration),
             max_speed: MaxMovementSpeed(max_speed),
             damping: MovementDampingFactor(damping),
+            air_acceleration: AirAcceleration(acceleration),
+            air_max_speed: AirMaxMovementSpeed(max_speed),
+            air_damping: AirDampingFactor(damping),
             jump_impulse: JumpImpulse(jump_impulse),
             max_slope_angle: MaxSlopeAngle(max_slope_angle),
+            gravity: ControllerGravity(Vector::NEG_Y * 9.81),
+            jump_windows: JumpWindows {
+                coyote_ticks: 6,
+                buffer_ticks: 6,
+            },
         }
     }
+
+    /// The damping is the friction in the air, as the one passed to [`Self::new`] is on the ground.
+    pub(crate) fn with_air_control(
+        mut self,
+        acceleration: Scalar,
+        max_speed: Scalar,
+        damping: Scalar,
+    ) -> Self {
+        self.air_acceleration = AirAcceleration(acceleration);
+        self.air_max_speed = AirMaxMovementSpeed(max_speed);
+        self.air_damping = AirDampingFactor(damping);
+        self
+    }
+
+    pub(crate) fn with_gravity(mut self, gravity: Vector) -> Self {
+        self.gravity = ControllerGravity(gravity);
+        self
+    }
+
+    pub(crate) fn with_jump_windows(mut self, coyote_ticks: u16, buffer_ticks: u16) -> Self {
+        self.jump_windows = JumpWindows {
+            coyote_ticks,
+            buffer_ticks,
+        };
+        self
+    }
 }
 
 impl Default for CharacterMovementBundle {
     fn default() -> Self {
         Self::new(1.0, 10.0, 0.9, 7.0, PI * 0.45)
+            .with_air_control(0.2, 10.0, 0.99)
+            .with_gravity(Vector::NEG_Y * 9.81)
+            .with_jump_windows(6, 6)
     }
 }
 
@@ -244,6 +387,8 @@ pub enum PlayerActions {
     Move,
     LookAround,
     Jump,
+    Sprint,
+    Crouch,
 }
 
 impl Actionlike for PlayerActions {
@@ -0,0 +1,68 @@
+use bevy::prelude::*;
+use clap::ValueEnum;
+use lightyear::prelude::{ClientId, Tick, TickManager};
+use serde::{Deserialize, Serialize};
+
+use crate::my_states::GameState;
+
+/// Everything shown in the lobby, owned by the server and replicated to every client.
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Lobby {
+    pub players: Vec<LobbyPlayer>,
+    /// The player allowed to start the match: the host's own client, or else whoever joined first.
+    pub host: Option<ClientId>,
+    pub map: String,
+    pub mode: GameMode,
+    /// The tick every peer enters the match on, once the host started it.
+    pub start_tick: Option<Tick>,
+    /// Set by the server once the start tick passed, so late joiners go straight in.
+    pub started: bool,
+}
+
+impl Lobby {
+    pub fn everyone_ready(&self) -> bool {
+        self.players.iter().all(|player| player.ready)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LobbyPlayer {
+    pub client_id: ClientId,
+    pub ready: bool,
+}
+
+/// What kind of match the lobby is for, picked by the server's settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ValueEnum)]
+pub enum GameMode {
+    /// Run around the map, nothing else.
+    #[default]
+    FreeRoam,
+    /// Everyone spawns as far away from the others as possible.
+    Deathmatch,
+}
+
+/// Sent by a client to change its ready flag in the lobby.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SetReady(pub bool);
+
+/// Sent by the lobby's host to start the match once everyone is ready.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StartMatch;
+
+/// Moves the server and every client from the lobby into the match on the start tick.
+pub(crate) fn enter_match(
+    lobby: Option<Res<Lobby>>,
+    tick_manager: Res<TickManager>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let Some(lobby) = lobby else {
+        return;
+    };
+    let due = lobby.started
+        || lobby
+            .start_tick
+            .is_some_and(|start_tick| tick_manager.tick() >= start_tick);
+    if due {
+        next_state.set(GameState::Started { paused: false });
+    }
+}
@@ -2,23 +2,40 @@ use std::time::Duration;
 
 use avian3d::prelude::*;
 use bevy::prelude::*;
+use chat::{ChatBroadcast, ChatMessage};
+use checksum::{ChecksumChannel, ChecksumMessage};
 use client::{ComponentSyncMode, NetworkingState as ClientNetworkingState};
 use lib::{
-    Channel1, FixedSet, PhysicalPlayerBodyMarker, PhysicalPlayerHeadMarker, PlayerActions,
-    PlayerId, SERVER_REPLICATION_INTERVAL,
+    Channel1, ConnectionRejected, FixedSet, JoinRequest, MapSelection, PhysicalPlayerBodyMarker,
+    PhysicalPlayerHeadMarker, PlayerActions, PlayerId, Respawn, SessionAccepted, TeleportCount,
+    SERVER_REPLICATION_INTERVAL,
 };
 use lightyear::{
     prelude::*,
     utils::avian3d::{position, rotation},
 };
+use lobby::{enter_match, Lobby, SetReady, StartMatch};
+use physics::{
+    character_controller::CharacterControllerPlugin, CharacterControllerMode, GroundVelocity,
+    JumpTimers, MovementState,
+};
 use renderer::MyRendererPlugin;
 use server::NetworkingState as ServerNetworkingState;
 
-use crate::{my_states::GameState, FIXED_TIMESTEP_HZ};
+use crate::{
+    lightyear::net_stats::{Heartbeat, HeartbeatChannel},
+    map::MapTick,
+    my_states::GameState,
+    FIXED_TIMESTEP_HZ,
+};
 
+pub mod chat;
+pub mod checksum;
 pub mod lib;
+pub mod lobby;
 pub mod movement;
 pub mod physics;
+pub mod recording;
 mod renderer;
 
 pub struct MySharedPlugin;
@@ -27,28 +44,20 @@ impl Plugin for MySharedPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins((
             MyRendererPlugin,
+            CharacterControllerPlugin,
             LeafwingInputPlugin::<PlayerActions>::default(),
         ))
-        .configure_sets(
-            FixedUpdate,
-            (
-                // make sure that any physics simulation happens after the Main SystemSet
-                // (where we apply user's actions)
-                (
-                    PhysicsSet::Prepare,
-                    PhysicsSet::StepSimulation,
-                    PhysicsSet::Sync,
-                )
-                    .in_set(FixedSet::Physics),
-                (FixedSet::Main, FixedSet::Physics).chain(),
-            ),
-        )
-        .add_systems(OnEnter(ClientNetworkingState::Connected), go_ingame)
-        .add_systems(OnEnter(ServerNetworkingState::Started), go_ingame);
+        .add_systems(OnEnter(ClientNetworkingState::Connected), go_to_lobby)
+        .add_systems(OnEnter(ServerNetworkingState::Started), go_to_lobby)
+        .add_systems(Update, enter_match.run_if(in_state(GameState::Lobby)))
+        .add_systems(FixedFirst, update_map_tick);
+
+        configure_fixed_sets(app);
 
         app.register_type::<PlayerId>()
             .register_type::<PhysicalPlayerHeadMarker>()
-            .register_type::<PhysicalPlayerBodyMarker>();
+            .register_type::<PhysicalPlayerBodyMarker>()
+            .register_type::<TeleportCount>();
 
         app.register_component::<PlayerId>(ChannelDirection::ServerToClient)
             .add_prediction(ComponentSyncMode::Once)
@@ -65,6 +74,24 @@ impl Plugin for MySharedPlugin {
             .add_linear_interpolation_fn()
             .add_map_entities();
 
+        // picked by the client that spawns the body, so it travels with the pre-predicted entity
+        app.register_component::<CharacterControllerMode>(ChannelDirection::Bidirectional)
+            .add_prediction(ComponentSyncMode::Once)
+            .add_interpolation(ComponentSyncMode::Once);
+
+        app.register_component::<MovementState>(ChannelDirection::ServerToClient)
+            .add_prediction(ComponentSyncMode::Full)
+            .add_interpolation(ComponentSyncMode::Simple);
+
+        app.register_component::<JumpTimers>(ChannelDirection::ServerToClient)
+            .add_prediction(ComponentSyncMode::Full);
+
+        app.register_component::<GroundVelocity>(ChannelDirection::ServerToClient)
+            .add_prediction(ComponentSyncMode::Full);
+
+        app.register_component::<TeleportCount>(ChannelDirection::ServerToClient)
+            .add_prediction(ComponentSyncMode::Full);
+
         app.register_component::<Name>(ChannelDirection::ServerToClient)
             .add_prediction(ComponentSyncMode::Once)
             .add_interpolation(ComponentSyncMode::Once);
@@ -74,11 +101,37 @@ impl Plugin for MySharedPlugin {
             ..default()
         });
 
+        app.register_message::<ConnectionRejected>(ChannelDirection::ServerToClient);
+        app.register_message::<MapSelection>(ChannelDirection::ServerToClient);
+        app.register_message::<Respawn>(ChannelDirection::ClientToServer);
+        app.register_message::<JoinRequest>(ChannelDirection::ClientToServer);
+        app.register_message::<SessionAccepted>(ChannelDirection::ServerToClient);
+        app.register_message::<SetReady>(ChannelDirection::ClientToServer);
+        app.register_message::<StartMatch>(ChannelDirection::ClientToServer);
+        app.register_resource::<Lobby>(ChannelDirection::ServerToClient);
+        app.register_message::<ChatMessage>(ChannelDirection::ClientToServer);
+        app.register_message::<ChatBroadcast>(ChannelDirection::ServerToClient);
+
+        app.add_channel::<ChecksumChannel>(ChannelSettings {
+            mode: ChannelMode::SequencedUnreliable,
+            ..default()
+        });
+
+        app.register_message::<ChecksumMessage>(ChannelDirection::ServerToClient);
+
+        app.add_channel::<HeartbeatChannel>(ChannelSettings {
+            mode: ChannelMode::UnorderedUnreliable,
+            ..default()
+        });
+
+        app.register_message::<Heartbeat>(ChannelDirection::ServerToClient);
+
         // General Physics stuff
         app.register_component::<Position>(ChannelDirection::ServerToClient)
             .add_prediction(ComponentSyncMode::Full)
             .add_interpolation(ComponentSyncMode::Full)
             .add_interpolation_fn(position::lerp)
+            // the client snaps this correction when the server teleported the body, see `TeleportCount`
             .add_correction_fn(position::lerp);
 
         app.register_component::<Rotation>(ChannelDirection::ServerToClient)
@@ -97,16 +150,45 @@ impl Plugin for MySharedPlugin {
     }
 }
 
-fn go_ingame(mut next_state: ResMut<NextState<GameState>>) {
-    next_state.set(GameState::Started { paused: false });
+pub(crate) fn configure_fixed_sets(app: &mut App) {
+    app.configure_sets(
+        FixedUpdate,
+        (
+            // make sure that any physics simulation happens after the Main SystemSet
+            // (where we apply user's actions)
+            (
+                PhysicsSet::Prepare,
+                PhysicsSet::StepSimulation,
+                PhysicsSet::Sync,
+            )
+                .in_set(FixedSet::Physics),
+            (FixedSet::Main, FixedSet::Physics).chain(),
+        ),
+    );
+}
+
+/// Moving platforms are posed for the tick being simulated, also while replaying a rollback.
+fn update_map_tick(
+    tick_manager: Res<TickManager>,
+    rollback: Option<Res<client::Rollback>>,
+    mut map_tick: ResMut<MapTick>,
+) {
+    map_tick.0 = rollback
+        .as_ref()
+        .map(|rb| tick_manager.tick_or_rollback_tick(rb))
+        .unwrap_or(tick_manager.tick());
+}
+
+fn go_to_lobby(mut next_state: ResMut<NextState<GameState>>) {
+    next_state.set(GameState::Lobby);
 }
 
-pub fn shared_config() -> SharedConfig {
+pub fn shared_config(mode: Mode) -> SharedConfig {
     SharedConfig {
         server_replication_send_interval: SERVER_REPLICATION_INTERVAL,
         tick: TickConfig {
             tick_duration: Duration::from_secs_f64(1.0 / FIXED_TIMESTEP_HZ),
         },
-        mode: Mode::HostServer,
+        mode,
     }
 }
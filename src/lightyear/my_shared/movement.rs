@@ -1,45 +1,110 @@
 use avian3d::prelude::*;
-use bevy::prelude::*;
+use bevy::{ecs::query::QueryData, prelude::*};
 use leafwing_input_manager::prelude::ActionState;
 
 use super::{
     lib::{PhysicalPlayerBodyMarker, PhysicalPlayerHeadMarker, PlayerActions},
-    physics::{JumpImpulse, MaxMovementSpeed, MovementAcceleration},
+    physics::{
+        AirAcceleration, AirMaxMovementSpeed, CeilingAbove, Grounded, JumpImpulse, JumpTimers,
+        JumpWindows, MaxMovementSpeed, MovementAcceleration, MovementState,
+    },
 };
 
-pub fn shared_movement(
-    transform: &Transform,
-    jump_impulse: &JumpImpulse,
-    movement_acceleration: &MovementAcceleration,
-    max_speed: &MaxMovementSpeed,
-    linear_velocity: &mut LinearVelocity,
-    body_rotation: &mut Rotation,
-    body: &mut PhysicalPlayerBodyMarker,
+/// The components of a player body that [`shared_movement`] reads and writes.
+#[derive(QueryData)]
+#[query_data(mutable)]
+pub(crate) struct CharacterQuery {
+    pub(crate) transform: &'static Transform,
+    pub(crate) movement_acceleration: &'static MovementAcceleration,
+    pub(crate) max_speed: &'static MaxMovementSpeed,
+    pub(crate) air_acceleration: &'static AirAcceleration,
+    pub(crate) air_max_speed: &'static AirMaxMovementSpeed,
+    pub(crate) jump_impulse: &'static JumpImpulse,
+    pub(crate) jump_windows: &'static JumpWindows,
+    pub(crate) jump_timers: &'static mut JumpTimers,
+    pub(crate) linear_velocity: &'static mut LinearVelocity,
+    pub(crate) rotation: &'static mut Rotation,
+    pub(crate) body: &'static mut PhysicalPlayerBodyMarker,
+    pub(crate) movement_state: &'static mut MovementState,
+    pub(crate) is_grounded: Has<Grounded>,
+    pub(crate) has_ceiling_above: Has<CeilingAbove>,
+}
+
+/// The most a player can look around in one tick, in the units of the mouse motion:
+/// half a turn, far more than any real flick of the mouse.
+pub const MAX_LOOK_DELTA: f32 = 600.0;
+
+pub(crate) fn shared_movement(
+    character: &mut CharacterQueryItem,
     head_transform: &mut Transform,
     head: &mut PhysicalPlayerHeadMarker,
-    is_grounded: bool,
     action_state: &ActionState<PlayerActions>,
 ) {
-    let forward_vector = transform.forward();
-    let right_vector = transform.right();
-    let axis_pair = action_state.axis_pair(&PlayerActions::Move);
-    linear_velocity.x +=
-        (axis_pair.x * right_vector.x + axis_pair.y * forward_vector.x) * movement_acceleration.0;
-    linear_velocity.z +=
-        (axis_pair.x * right_vector.z + axis_pair.y * forward_vector.z) * movement_acceleration.0;
-
-    let max_speed = max_speed.0;
-    let speed = linear_velocity.length();
-    if speed > max_speed {
-        linear_velocity.x *= max_speed / speed;
-        linear_velocity.z *= max_speed / speed;
+    let linear_velocity = &mut character.linear_velocity;
+    // inputs come from the clients, so they are bounded before anything trusts them
+    let axis_pair = action_state
+        .axis_pair(&PlayerActions::Move)
+        .clamp_length_max(1.0);
+
+    let movement_state = character.movement_state.next(
+        action_state,
+        axis_pair,
+        linear_velocity.xz().length(),
+        character.is_grounded,
+        character.has_ceiling_above,
+    );
+    character.movement_state.set_if_neq(movement_state);
+
+    let forward_vector = character.transform.forward();
+    let right_vector = character.transform.right();
+    let wish_direction = Vec3::new(
+        axis_pair.x * right_vector.x + axis_pair.y * forward_vector.x,
+        0.0,
+        axis_pair.x * right_vector.z + axis_pair.y * forward_vector.z,
+    );
+    let speed_multiplier = movement_state.speed_multiplier();
+
+    if !character.is_grounded {
+        // only accelerate up to the air max speed in the direction we want to go, so the speed
+        // we took off with is kept but can't be turned around at full strength mid-air
+        let wish_speed =
+            character.air_max_speed.0 * speed_multiplier * wish_direction.length().min(1.0);
+        let wish_direction = wish_direction.normalize_or_zero();
+        let current_speed = linear_velocity.dot(wish_direction);
+        let added_speed = (wish_speed - current_speed)
+            .clamp(0.0, character.air_acceleration.0 * speed_multiplier);
+        linear_velocity.x += wish_direction.x * added_speed;
+        linear_velocity.z += wish_direction.z * added_speed;
+    } else if movement_state != MovementState::Sliding {
+        // not while sliding though, a slide keeps the speed it started with and only slows down
+        let acceleration = character.movement_acceleration.0 * speed_multiplier;
+        linear_velocity.x += wish_direction.x * acceleration;
+        linear_velocity.z += wish_direction.z * acceleration;
+
+        let max_speed = character.max_speed.0 * speed_multiplier;
+        let speed = linear_velocity.length();
+        if speed > max_speed {
+            linear_velocity.x *= max_speed / speed;
+            linear_velocity.z *= max_speed / speed;
+        }
     }
 
-    if is_grounded && action_state.just_pressed(&PlayerActions::Jump) {
-        linear_velocity.y = jump_impulse.0;
+    // still touching the ground right after a jump must not start a new coyote window
+    let is_grounded = character.is_grounded && linear_velocity.y <= 0.0;
+    let mut jump_timers = *character.jump_timers;
+    if jump_timers.tick(
+        action_state.pressed(&PlayerActions::Jump),
+        is_grounded,
+        character.jump_windows,
+    ) {
+        linear_velocity.y = character.jump_impulse.0;
     }
+    character.jump_timers.set_if_neq(jump_timers);
 
-    let camera_vector = action_state.axis_pair(&PlayerActions::LookAround) * 0.3;
+    let camera_vector = action_state
+        .axis_pair(&PlayerActions::LookAround)
+        .clamp_length_max(MAX_LOOK_DELTA)
+        * 0.3;
     let max_pitch: f32 = 89.9_f32.to_radians(); // Prevent flipping, slightly less than 90 degrees
     let min_pitch: f32 = -89.9_f32.to_radians(); // Slightly more than -90 degrees
 
@@ -48,10 +113,11 @@ pub fn shared_movement(
         .max(min_pitch);
     let head_rotation_quat = Quat::from_axis_angle(Vec3::X, head.pitch);
 
-    body.yaw += -camera_vector.x.to_radians();
-    let body_rotation_quat = Quat::from_axis_angle(Vec3::Y, body.yaw);
+    character.body.yaw += -camera_vector.x.to_radians();
+    let body_rotation_quat = Quat::from_axis_angle(Vec3::Y, character.body.yaw);
 
     // Accumulate rotation by multiplying the current quaternion by the new increment
     head_transform.rotation = head_rotation_quat;
-    body_rotation.0 = body_rotation_quat;
+    head_transform.translation.y = movement_state.head_height();
+    character.rotation.0 = body_rotation_quat;
 }
@@ -0,0 +1,396 @@
+use avian3d::{
+    math::{Quaternion, Scalar, Vector},
+    prelude::*,
+};
+use bevy::prelude::*;
+
+use super::{
+    AirDampingFactor, CeilingAbove, CharacterControllerMode, ControllerGravity, GroundVelocity,
+    Grounded, MaxSlopeAngle, MovementDampingFactor, MovementState, SLIDE_DAMPING_FACTOR,
+};
+use crate::{
+    lightyear::my_shared::lib::{ground_caster, FixedSet, PhysicalPlayerBodyMarker},
+    map::MovingPlatform,
+};
+
+/// The physics of the character controller that is not driven by inputs.
+///
+/// Added on the client and the server alike, so the body the client predicts and the one the
+/// server simulates always go through the same steps in the same order.
+pub struct CharacterControllerPlugin;
+
+impl Plugin for CharacterControllerPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<CharacterControllerMode>()
+            .register_type::<MovementState>()
+            .add_systems(
+                FixedUpdate,
+                (
+                    sync_rigid_body.before(FixedSet::Main),
+                    (
+                        update_collider,
+                        (update_grounded, update_ceiling),
+                        ride_platforms,
+                        apply_gravity,
+                        apply_movement_damping,
+                        move_kinematic_controllers,
+                    )
+                        .chain()
+                        .in_set(FixedSet::Physics)
+                        .before(PhysicsSet::Prepare),
+                ),
+            );
+    }
+}
+
+/// Gap kept between a kinematic body and what it slides along, so casts never start inside it.
+const SKIN_WIDTH: Scalar = 0.01;
+/// How many times a kinematic body may change direction in one tick.
+const MAX_SLIDES: usize = 4;
+/// The highest ledge a kinematic body walks onto without jumping.
+const STEP_HEIGHT: Scalar = 0.35;
+/// How far below its feet a grounded kinematic body still finds the ground to stick to.
+const SNAP_DISTANCE: Scalar = 0.25;
+
+/// Bodies get `RigidBody::Dynamic` from [`PhysicsBundle`](crate::lightyear::my_shared::lib::PhysicsBundle),
+/// this switches them to what their [`CharacterControllerMode`] asks for.
+fn sync_rigid_body(
+    mut query: Query<
+        (&CharacterControllerMode, &mut RigidBody),
+        Or<(Changed<CharacterControllerMode>, Added<RigidBody>)>,
+    >,
+) {
+    for (mode, mut rigid_body) in &mut query {
+        rigid_body.set_if_neq(mode.rigid_body());
+    }
+}
+
+/// Gives bodies the collider of their [`MovementState`], also after a rollback restored it.
+///
+/// The other players' bodies on a client only get a [`PhysicsBundle`](crate::lightyear::my_shared::lib::PhysicsBundle),
+/// without a ground caster, but their collider changes all the same.
+fn update_collider(
+    mut query: Query<
+        (&MovementState, &mut Collider, Option<&mut ShapeCaster>),
+        Or<(Changed<MovementState>, Added<Collider>)>,
+    >,
+) {
+    for (movement_state, mut collider, caster) in &mut query {
+        *collider = movement_state.collider();
+        if let Some(mut caster) = caster {
+            *caster = ground_caster(collider.clone());
+        }
+    }
+}
+
+/// Updates the [`CeilingAbove`] status of crouched characters.
+fn update_ceiling(
+    mut commands: Commands,
+    spatial_query: SpatialQuery,
+    query: Query<(Entity, &MovementState, &Position, &Rotation), With<PhysicalPlayerBodyMarker>>,
+) {
+    let standing_collider = MovementState::Walking.collider();
+
+    for (entity, movement_state, position, rotation) in &query {
+        let has_ceiling_above = movement_state.is_crouched()
+            && !spatial_query
+                .shape_intersections(
+                    &standing_collider,
+                    position.0,
+                    rotation.0,
+                    SpatialQueryFilter::from_excluded_entities([entity]),
+                )
+                .is_empty();
+
+        if has_ceiling_above {
+            commands.entity(entity).insert(CeilingAbove);
+        } else {
+            commands.entity(entity).remove::<CeilingAbove>();
+        }
+    }
+}
+
+/// Updates the [`Grounded`] status for character controllers, and the [`GroundVelocity`]
+/// of the moving platform they stand on.
+fn update_grounded(
+    mut commands: Commands,
+    mut query: Query<
+        (
+            Entity,
+            &ShapeHits,
+            &Position,
+            &Rotation,
+            Option<&MaxSlopeAngle>,
+            &mut GroundVelocity,
+            &mut LinearVelocity,
+        ),
+        With<PhysicalPlayerBodyMarker>,
+    >,
+    platform_query: Query<
+        (&Position, &LinearVelocity, &AngularVelocity),
+        (With<MovingPlatform>, Without<PhysicalPlayerBodyMarker>),
+    >,
+) {
+    for (
+        entity,
+        hits,
+        position,
+        rotation,
+        max_slope_angle,
+        mut ground_velocity,
+        mut linear_velocity,
+    ) in &mut query
+    {
+        // The character is grounded if the shape caster has a hit with a normal
+        // that isn't too steep.
+        let ground = hits.iter().find(|hit| {
+            if let Some(angle) = max_slope_angle {
+                (rotation * -hit.normal2).angle_between(Vector::Y).abs() <= angle.0
+            } else {
+                true
+            }
+        });
+
+        if ground.is_some() {
+            commands.entity(entity).insert(Grounded);
+        } else {
+            commands.entity(entity).remove::<Grounded>();
+        }
+
+        // the velocity of the platform at the point we stand on
+        let platform_velocity = ground
+            .and_then(|hit| platform_query.get(hit.entity).ok())
+            .map_or(Vector::ZERO, |(platform_position, linear, angular)| {
+                linear.0 + angular.0.cross(position.0 - platform_position.0)
+            });
+        if platform_velocity == Vector::ZERO && ground_velocity.0 != Vector::ZERO {
+            // keep the platform's momentum when jumping or walking off it
+            linear_velocity.0 += ground_velocity.0;
+        }
+        ground_velocity.set_if_neq(GroundVelocity(platform_velocity));
+    }
+}
+
+/// Carries characters along with the platform they stand on.
+///
+/// The carried distance is not part of their own velocity, so their movement, damping and
+/// collide-and-slide work the same on a platform as on the ground.
+fn ride_platforms(
+    time: Res<Time<Fixed>>,
+    mut query: Query<(&GroundVelocity, &mut Position), With<PhysicalPlayerBodyMarker>>,
+) {
+    let delta_time = time.timestep().as_secs_f32();
+
+    for (ground_velocity, mut position) in &mut query {
+        if ground_velocity.0 != Vector::ZERO {
+            position.0 += ground_velocity.0 * delta_time;
+        }
+    }
+}
+
+/// Applies [`ControllerGravity`] to character controllers.
+///
+/// Their bodies have a `GravityScale` of zero, so this is the only gravity they feel.
+fn apply_gravity(
+    time: Res<Time<Fixed>>,
+    mut query: Query<(&ControllerGravity, &mut LinearVelocity)>,
+) {
+    let delta_time = time.timestep().as_secs_f32();
+
+    for (gravity, mut linear_velocity) in &mut query {
+        linear_velocity.0 += gravity.0 * delta_time;
+    }
+}
+
+/// Slows down movement in the XZ plane, with ground friction or air resistance.
+fn apply_movement_damping(
+    mut query: Query<(
+        &MovementDampingFactor,
+        Option<&AirDampingFactor>,
+        Option<&MovementState>,
+        Has<Grounded>,
+        &mut LinearVelocity,
+    )>,
+) {
+    for (damping_factor, air_damping_factor, movement_state, is_grounded, mut linear_velocity) in
+        &mut query
+    {
+        let damping_factor = match (movement_state, air_damping_factor) {
+            (Some(MovementState::Sliding), _) => SLIDE_DAMPING_FACTOR,
+            (_, Some(air_damping_factor)) if !is_grounded => air_damping_factor.0,
+            _ => damping_factor.0,
+        };
+        // We could use `LinearDamping`, but we don't want to dampen movement along the Y axis
+        linear_velocity.x *= damping_factor;
+        linear_velocity.z *= damping_factor;
+    }
+}
+
+/// Moves kinematic bodies with collide-and-slide, then steps them up small ledges
+/// and snaps them to the ground.
+fn move_kinematic_controllers(
+    time: Res<Time<Fixed>>,
+    spatial_query: SpatialQuery,
+    mut query: Query<
+        (
+            Entity,
+            &CharacterControllerMode,
+            &Collider,
+            &mut Position,
+            &Rotation,
+            &mut LinearVelocity,
+            Option<&MaxSlopeAngle>,
+            Has<Grounded>,
+        ),
+        With<PhysicalPlayerBodyMarker>,
+    >,
+) {
+    let delta_time = time.timestep().as_secs_f32();
+
+    for (
+        entity,
+        mode,
+        collider,
+        mut position,
+        rotation,
+        mut linear_velocity,
+        max_slope_angle,
+        is_grounded,
+    ) in &mut query
+    {
+        if *mode != CharacterControllerMode::Kinematic {
+            continue;
+        }
+
+        let caster = KinematicCaster {
+            spatial_query: &spatial_query,
+            collider,
+            rotation: rotation.0,
+            filter: SpatialQueryFilter::from_excluded_entities([entity]),
+            max_slope_angle: max_slope_angle.map(|angle| angle.0),
+        };
+
+        let (displacement, mut velocity) =
+            caster.collide_and_slide(position.0, linear_velocity.0, delta_time);
+        let mut target = position.0 + displacement;
+
+        if is_grounded && velocity.y <= 0.0 {
+            // a wall in the way may be a ledge we can walk onto
+            let blocked = horizontal(linear_velocity.0) * delta_time - horizontal(displacement);
+            if let Some(step) = caster.step_up(target, blocked) {
+                target = step;
+                velocity = horizontal(linear_velocity.0);
+            } else if let Some(drop) = caster.ground_below(target, SNAP_DISTANCE) {
+                // stay on the ground when walking down a slope or over a small drop
+                target.y -= drop;
+                velocity.y = 0.0;
+            }
+        }
+
+        // avian still integrates the velocity we leave, so start from where that ends up at `target`
+        position.0 = target - velocity * delta_time;
+        linear_velocity.0 = velocity;
+    }
+}
+
+fn horizontal(vector: Vector) -> Vector {
+    Vector::new(vector.x, 0.0, vector.z)
+}
+
+struct KinematicCaster<'a, 'w, 's> {
+    spatial_query: &'a SpatialQuery<'w, 's>,
+    collider: &'a Collider,
+    rotation: Quaternion,
+    filter: SpatialQueryFilter,
+    max_slope_angle: Option<Scalar>,
+}
+
+impl KinematicCaster<'_, '_, '_> {
+    /// Distance the shape can travel from `origin` along `direction` before hitting something,
+    /// and the normal of what it hits.
+    fn cast(
+        &self,
+        origin: Vector,
+        direction: Vector,
+        distance: Scalar,
+    ) -> Option<(Scalar, Vector)> {
+        let direction = Dir3::new(direction).ok()?;
+        self.spatial_query
+            .cast_shape(
+                self.collider,
+                origin,
+                self.rotation,
+                direction,
+                distance + SKIN_WIDTH,
+                true,
+                self.filter.clone(),
+            )
+            .map(|hit| ((hit.time_of_impact - SKIN_WIDTH).max(0.0), hit.normal1))
+    }
+
+    fn is_walkable(&self, normal: Vector) -> bool {
+        self.max_slope_angle
+            .map_or(true, |angle| normal.angle_between(Vector::Y).abs() <= angle)
+    }
+
+    /// Returns how far the body gets this tick and the velocity it keeps afterwards,
+    /// with everything pointing into the surfaces it hit taken out.
+    fn collide_and_slide(
+        &self,
+        origin: Vector,
+        mut velocity: Vector,
+        delta_time: Scalar,
+    ) -> (Vector, Vector) {
+        let mut displacement = Vector::ZERO;
+        let mut remaining = velocity * delta_time;
+
+        for _ in 0..MAX_SLIDES {
+            let distance = remaining.length();
+            if distance <= Scalar::EPSILON {
+                break;
+            }
+            let direction = remaining / distance;
+            let Some((travel, normal)) = self.cast(origin + displacement, direction, distance)
+            else {
+                displacement += remaining;
+                break;
+            };
+
+            displacement += direction * travel;
+            remaining -= direction * travel;
+            remaining -= normal * remaining.dot(normal);
+            velocity -= normal * velocity.dot(normal).min(0.0);
+        }
+
+        (displacement, velocity)
+    }
+
+    /// Where the body ends up if it can climb onto a ledge of at most [`STEP_HEIGHT`]
+    /// by moving `horizontal` from `origin`.
+    fn step_up(&self, origin: Vector, horizontal: Vector) -> Option<Vector> {
+        let distance = horizontal.length();
+        if distance <= SKIN_WIDTH {
+            return None;
+        }
+        // room above our head
+        if self.cast(origin, Vector::Y, STEP_HEIGHT).is_some() {
+            return None;
+        }
+        let raised = origin + Vector::Y * STEP_HEIGHT;
+        // room on top of the ledge
+        if self.cast(raised, horizontal / distance, distance).is_some() {
+            return None;
+        }
+        let forward = raised + horizontal;
+        let drop = self.ground_below(forward, STEP_HEIGHT)?;
+        // moving up by less than the skin width is not a ledge, just the wall we hit
+        (drop < STEP_HEIGHT - SKIN_WIDTH).then(|| forward - Vector::Y * drop)
+    }
+
+    /// Distance down to walkable ground within `max_distance`.
+    fn ground_below(&self, origin: Vector, max_distance: Scalar) -> Option<Scalar> {
+        self.cast(origin, Vector::NEG_Y, max_distance)
+            .filter(|(_, normal)| self.is_walkable(*normal))
+            .map(|(drop, _)| drop)
+    }
+}
@@ -1,5 +1,15 @@
-use avian3d::math::{Scalar, Vector};
+use avian3d::{
+    math::{Quaternion, Scalar, Vector},
+    prelude::{Collider, RigidBody},
+};
 use bevy::prelude::*;
+use clap::ValueEnum;
+use leafwing_input_manager::prelude::ActionState;
+use serde::{Deserialize, Serialize};
+
+use super::lib::PlayerActions;
+
+pub mod character_controller;
 
 /// A marker component indicating that an entity is on the ground.
 #[derive(Component, Reflect, Default)]
@@ -7,6 +17,17 @@ use bevy::prelude::*;
 #[component(storage = "SparseSet")]
 pub(crate) struct Grounded;
 
+/// The velocity of the moving platform a character stands on, zero anywhere else.
+#[derive(Component, Reflect, Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq)]
+#[reflect(Component)]
+pub struct GroundVelocity(pub Vector);
+
+/// A marker component for crouched characters that have no room to stand up.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+#[component(storage = "SparseSet")]
+pub(crate) struct CeilingAbove;
+
 /// The acceleration used for character movement.
 #[derive(Component, Reflect, Default)]
 #[reflect(Component)]
@@ -17,16 +38,93 @@ pub(crate) struct MovementAcceleration(pub(crate) Scalar);
 #[reflect(Component)]
 pub(crate) struct MaxMovementSpeed(pub(crate) Scalar);
 
-/// The damping factor used for slowing down movement.
+/// The ground friction: the share of its horizontal velocity a body keeps every tick.
 #[derive(Component, Reflect, Default)]
 #[reflect(Component)]
 pub(crate) struct MovementDampingFactor(pub(crate) Scalar);
 
+/// The acceleration used for character movement while airborne.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub(crate) struct AirAcceleration(pub(crate) Scalar);
+
+/// The horizontal speed a character can reach on its own while airborne.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub(crate) struct AirMaxMovementSpeed(pub(crate) Scalar);
+
+/// The air friction: the share of its horizontal velocity a body keeps every tick while airborne.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub(crate) struct AirDampingFactor(pub(crate) Scalar);
+
 /// The strength of a jump.
 #[derive(Component, Reflect, Default)]
 #[reflect(Component)]
 pub(crate) struct JumpImpulse(pub(crate) Scalar);
 
+/// How many ticks a character may still jump after walking off a ledge (coyote time),
+/// and how many ticks a jump pressed before landing is remembered for (jump buffer).
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub(crate) struct JumpWindows {
+    pub(crate) coyote_ticks: u16,
+    pub(crate) buffer_ticks: u16,
+}
+
+/// Ticks counted by a character for its [`JumpWindows`].
+///
+/// Predicted like the rest of the movement state, so a rollback replays jumps exactly
+/// instead of relying on `just_pressed`, which only holds for the tick the input arrived on.
+#[derive(Component, Reflect, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[reflect(Component)]
+pub struct JumpTimers {
+    ticks_since_grounded: u16,
+    ticks_since_jump_pressed: u16,
+    jump_held: bool,
+}
+
+impl Default for JumpTimers {
+    fn default() -> Self {
+        Self {
+            ticks_since_grounded: u16::MAX,
+            ticks_since_jump_pressed: u16::MAX,
+            jump_held: false,
+        }
+    }
+}
+
+impl JumpTimers {
+    /// Advances the timers by one tick, returns whether the character jumps on it.
+    pub(crate) fn tick(
+        &mut self,
+        jump_pressed: bool,
+        is_grounded: bool,
+        windows: &JumpWindows,
+    ) -> bool {
+        self.ticks_since_grounded = if is_grounded {
+            0
+        } else {
+            self.ticks_since_grounded.saturating_add(1)
+        };
+        self.ticks_since_jump_pressed = if jump_pressed && !self.jump_held {
+            0
+        } else {
+            self.ticks_since_jump_pressed.saturating_add(1)
+        };
+        self.jump_held = jump_pressed;
+
+        let jumps = self.ticks_since_grounded <= windows.coyote_ticks
+            && self.ticks_since_jump_pressed <= windows.buffer_ticks;
+        if jumps {
+            // both windows are used up by this jump
+            self.ticks_since_grounded = u16::MAX;
+            self.ticks_since_jump_pressed = u16::MAX;
+        }
+        jumps
+    }
+}
+
 /// The gravitational acceleration used for a character controller.
 #[derive(Component, Reflect, Default)]
 #[reflect(Component)]
@@ -38,3 +136,130 @@ pub(crate) struct ControllerGravity(pub(crate) Vector);
 #[derive(Component, Reflect, Default)]
 #[reflect(Component)]
 pub(crate) struct MaxSlopeAngle(pub(crate) Scalar);
+
+/// How the physics of a player body are resolved, chosen by the player that spawns it.
+#[derive(
+    Component,
+    Reflect,
+    Serialize,
+    Deserialize,
+    ValueEnum,
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+)]
+#[reflect(Component)]
+pub enum CharacterControllerMode {
+    /// A rotation locked dynamic body, pushed around by the physics solver.
+    #[default]
+    Dynamic,
+    /// A kinematic body that slides along what it hits, steps onto small ledges
+    /// and sticks to the ground when walking down slopes.
+    Kinematic,
+}
+
+impl CharacterControllerMode {
+    pub fn rigid_body(&self) -> RigidBody {
+        match self {
+            CharacterControllerMode::Dynamic => RigidBody::Dynamic,
+            CharacterControllerMode::Kinematic => RigidBody::Kinematic,
+        }
+    }
+}
+
+const PLAYER_RADIUS: Scalar = 0.4;
+const STANDING_LENGTH: Scalar = 1.0;
+const CROUCHED_LENGTH: Scalar = 0.2;
+/// Where the head sits above the body's origin while standing.
+const STANDING_HEAD_HEIGHT: Scalar = 2.0;
+/// The horizontal speed a sprint needs to turn into a slide when crouching.
+const SLIDE_START_SPEED: Scalar = 12.0;
+/// A slide slower than this ends in a crouch.
+const SLIDE_END_SPEED: Scalar = 4.0;
+/// The damping factor used instead of [`MovementDampingFactor`] while sliding.
+pub(crate) const SLIDE_DAMPING_FACTOR: Scalar = 0.98;
+
+/// What a character is doing, which decides how fast it moves and how tall it is.
+///
+/// Changes to it go through `shared_movement`, so they are predicted and replayed
+/// during rollback like the rest of the movement.
+#[derive(
+    Component, Reflect, Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq,
+)]
+#[reflect(Component)]
+pub enum MovementState {
+    #[default]
+    Walking,
+    Sprinting,
+    Crouching,
+    /// Crouched while fast, keeping the speed and losing it slowly.
+    Sliding,
+}
+
+impl MovementState {
+    /// Scales [`MovementAcceleration`] and [`MaxMovementSpeed`].
+    pub fn speed_multiplier(&self) -> Scalar {
+        match self {
+            MovementState::Walking | MovementState::Sliding => 1.0,
+            MovementState::Sprinting => 1.6,
+            MovementState::Crouching => 0.5,
+        }
+    }
+
+    pub fn is_crouched(&self) -> bool {
+        matches!(self, MovementState::Crouching | MovementState::Sliding)
+    }
+
+    /// The body's collider. A crouched capsule is shorter and keeps its bottom where the
+    /// standing one has it, so crouching or standing up never moves the feet.
+    pub fn collider(&self) -> Collider {
+        if !self.is_crouched() {
+            return Collider::capsule(PLAYER_RADIUS, STANDING_LENGTH);
+        }
+        Collider::compound(vec![(
+            Vector::NEG_Y * (STANDING_LENGTH - CROUCHED_LENGTH) / 2.0,
+            Quaternion::IDENTITY,
+            Collider::capsule(PLAYER_RADIUS, CROUCHED_LENGTH),
+        )])
+    }
+
+    pub fn head_height(&self) -> Scalar {
+        if self.is_crouched() {
+            STANDING_HEAD_HEIGHT - (STANDING_LENGTH - CROUCHED_LENGTH)
+        } else {
+            STANDING_HEAD_HEIGHT
+        }
+    }
+
+    /// The state this tick's inputs lead to.
+    pub(crate) fn next(
+        &self,
+        action_state: &ActionState<PlayerActions>,
+        movement: Vec2,
+        horizontal_speed: Scalar,
+        is_grounded: bool,
+        has_ceiling_above: bool,
+    ) -> Self {
+        if !action_state.pressed(&PlayerActions::Crouch) && !has_ceiling_above {
+            return if action_state.pressed(&PlayerActions::Sprint) && movement.y > 0.0 {
+                MovementState::Sprinting
+            } else {
+                MovementState::Walking
+            };
+        }
+
+        let keeps_sliding = match self {
+            MovementState::Sprinting => horizontal_speed >= SLIDE_START_SPEED,
+            MovementState::Sliding => horizontal_speed > SLIDE_END_SPEED,
+            _ => false,
+        };
+        if is_grounded && keeps_sliding {
+            MovementState::Sliding
+        } else {
+            MovementState::Crouching
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use avian3d::math::{Quaternion, Scalar, Vector};
+use bevy::prelude::Event;
+use leafwing_input_manager::prelude::ActionState;
+use lightyear::prelude::{ClientId, Tick};
+use serde::{Deserialize, Serialize};
+
+use crate::map::DEFAULT_MAP;
+
+use super::{
+    lib::PlayerActions,
+    physics::{CharacterControllerMode, JumpTimers, MovementState},
+};
+
+/// Starts recording the local player's inputs, or stops and saves the recording, as F5 does.
+#[derive(Event, Debug, Clone, Default)]
+pub struct ToggleRecording {
+    /// Where to save the recording when it stops, `recordings/recording-<timestamp>.ron` if unset.
+    pub path: Option<PathBuf>,
+}
+
+/// The inputs of the players, tick by tick, and the world they started from,
+/// so that the movement can be simulated again without a network.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Recording {
+    pub fixed_timestep_hz: f64,
+    /// Recordings made before maps were loaded from assets all used the default one.
+    #[serde(default = "default_map")]
+    pub map: String,
+    /// The tick the moving platforms were posed for when the recording started,
+    /// the last one simulated before the first of [`Recording::ticks`].
+    #[serde(default)]
+    pub map_tick: Tick,
+    /// The recorded player, whose positions are checked when replaying.
+    pub initial_state: PlayerSnapshot,
+    /// Everyone else who was there when the recording started, players joining later are left out.
+    #[serde(default)]
+    pub other_players: Vec<OtherPlayer>,
+    pub ticks: Vec<RecordedTick>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct OtherPlayer {
+    pub player_id: ClientId,
+    pub initial_state: PlayerSnapshot,
+}
+
+/// The physics state of a player body and its head.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct PlayerSnapshot {
+    pub position: Vector,
+    pub rotation: Quaternion,
+    pub linear_velocity: Vector,
+    pub yaw: Scalar,
+    pub pitch: Scalar,
+    /// Recordings made before the kinematic controller existed used a dynamic body.
+    #[serde(default)]
+    pub controller: CharacterControllerMode,
+    #[serde(default)]
+    pub movement_state: MovementState,
+    #[serde(default)]
+    pub jump_timers: JumpTimers,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RecordedTick {
+    pub tick: Tick,
+    pub action_state: ActionState<PlayerActions>,
+    /// Where the body ended up after this tick's physics step, the last time it was simulated:
+    /// a rollback into the tick overwrites what was predicted before the server's state arrived.
+    pub position: Vector,
+    /// The inputs of [`Recording::other_players`], in the same order.
+    #[serde(default)]
+    pub other_action_states: Vec<ActionState<PlayerActions>>,
+}
+
+fn default_map() -> String {
+    DEFAULT_MAP.to_string()
+}
+
+impl Recording {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        ron::from_str(&contents).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        fs::write(path, contents)
+    }
+}
@@ -0,0 +1,117 @@
+use std::{collections::VecDeque, time::Duration};
+
+use bevy::prelude::*;
+use lightyear::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// How often the network statistics are sampled.
+pub const SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How many samples are kept for the graphs.
+pub const GRAPH_SAMPLES: usize = 100;
+
+/// Carries the server's [`Heartbeat`]s, unreliable so that the missing ones tell the packet loss.
+#[derive(Channel)]
+pub struct HeartbeatChannel;
+
+/// Sent by the server to every client on every tick, only for them to count how many arrive.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Heartbeat {
+    pub tick: Tick,
+}
+
+/// The network numbers of our own connection at one point in time.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NetSample {
+    pub rtt_ms: f32,
+    pub jitter_ms: f32,
+    pub packet_loss_percent: f32,
+    pub kb_in_per_sec: f32,
+    pub kb_out_per_sec: f32,
+    pub tick: u16,
+    pub input_delay_ticks: u16,
+    /// How far our predicted tick is ahead of the last server tick we heard of.
+    pub ticks_ahead: i16,
+}
+
+/// The last [`GRAPH_SAMPLES`] samples of our connection, oldest first.
+#[derive(Resource, Default, Debug)]
+pub struct NetStats(pub VecDeque<NetSample>);
+
+impl NetStats {
+    pub fn push(&mut self, sample: NetSample) {
+        self.0.push_back(sample);
+        if self.0.len() > GRAPH_SAMPLES {
+            self.0.pop_front();
+        }
+    }
+
+    pub fn latest(&self) -> NetSample {
+        self.0.back().copied().unwrap_or_default()
+    }
+}
+
+/// One of the numbers in a [`NetSample`], as shown in the HUD.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetMetric {
+    Rtt,
+    Jitter,
+    PacketLoss,
+    BandwidthIn,
+    BandwidthOut,
+    Tick,
+    InputDelay,
+    TicksAhead,
+}
+
+impl NetMetric {
+    pub const ALL: [NetMetric; 8] = [
+        NetMetric::Rtt,
+        NetMetric::Jitter,
+        NetMetric::PacketLoss,
+        NetMetric::BandwidthIn,
+        NetMetric::BandwidthOut,
+        NetMetric::Tick,
+        NetMetric::InputDelay,
+        NetMetric::TicksAhead,
+    ];
+
+    pub fn value(&self, sample: &NetSample) -> f32 {
+        match self {
+            NetMetric::Rtt => sample.rtt_ms,
+            NetMetric::Jitter => sample.jitter_ms,
+            NetMetric::PacketLoss => sample.packet_loss_percent,
+            NetMetric::BandwidthIn => sample.kb_in_per_sec,
+            NetMetric::BandwidthOut => sample.kb_out_per_sec,
+            NetMetric::Tick => sample.tick as f32,
+            NetMetric::InputDelay => sample.input_delay_ticks as f32,
+            NetMetric::TicksAhead => sample.ticks_ahead as f32,
+        }
+    }
+
+    pub fn format(&self, sample: &NetSample) -> String {
+        match self {
+            NetMetric::Rtt => format!("RTT: {:.0} ms", sample.rtt_ms),
+            NetMetric::Jitter => format!("Jitter: {:.1} ms", sample.jitter_ms),
+            NetMetric::PacketLoss => format!("Loss: {:.1} %", sample.packet_loss_percent),
+            NetMetric::BandwidthIn => format!("In: {:.1} KB/s", sample.kb_in_per_sec),
+            NetMetric::BandwidthOut => format!("Out: {:.1} KB/s", sample.kb_out_per_sec),
+            NetMetric::Tick => format!("Tick: {}", sample.tick),
+            NetMetric::InputDelay => format!("Input delay: {} ticks", sample.input_delay_ticks),
+            NetMetric::TicksAhead => format!("Ahead: {} ticks", sample.ticks_ahead),
+        }
+    }
+}
+
+/// What the server knows about each client's connection, filled while hosting.
+#[derive(Resource, Default, Debug)]
+pub struct ServerNetStats(pub Vec<ClientNetStats>);
+
+#[derive(Debug, Clone, Copy)]
+pub struct ClientNetStats {
+    pub client_id: ClientId,
+    pub rtt: Duration,
+    pub jitter: Duration,
+    /// Inputs the server threw away or found forged, see `--kick-after-invalid-inputs`.
+    pub rejected_inputs: u32,
+}
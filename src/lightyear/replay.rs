@@ -0,0 +1,163 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use avian3d::{math::Vector, prelude::*};
+use bevy::{prelude::*, state::app::StatesPlugin, time::TimeUpdateStrategy};
+use leafwing_input_manager::prelude::ActionState;
+use lightyear::prelude::{ClientId, Tick};
+
+use super::my_shared::{
+    configure_fixed_sets,
+    lib::{
+        FixedSet, PhysicalPlayerBodyMarker, PhysicalPlayerHeadBundle, PhysicalPlayerHeadMarker,
+        PhysicalPlayerServerBodyBundle, PlayerActions, PlayerId,
+    },
+    movement::{shared_movement, CharacterQuery},
+    physics::character_controller::CharacterControllerPlugin,
+    recording::{PlayerSnapshot, Recording},
+};
+use crate::{
+    map::{MapRoot, MapTick, MyMapPlugin, SelectedMap},
+    my_states::{GameState, MyStatesPlugin},
+    MyPhysicsPlugin, FIXED_TIMESTEP_HZ,
+};
+
+pub use super::my_shared::recording;
+
+/// How long the recorded map may take to load before we give up.
+const MAP_LOAD_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The first tick at which the replayed body ended up somewhere else than during the recording.
+#[derive(Debug, Clone, Copy)]
+pub struct ReplayDivergence {
+    pub tick: Tick,
+    pub recorded: Vector,
+    pub replayed: Vector,
+}
+
+/// Simulates the recorded inputs of every player again in a headless app, without any networking,
+/// and returns the first tick where the recorded player ended up elsewhere than in the recording.
+///
+/// Fails if the map the recording was made on cannot be loaded.
+pub fn replay(recording: &Recording) -> Result<Option<ReplayDivergence>, String> {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        StatesPlugin,
+        TransformPlugin,
+        HierarchyPlugin,
+        AssetPlugin::default(),
+        MyPhysicsPlugin,
+        MyStatesPlugin,
+        MyMapPlugin,
+        CharacterControllerPlugin,
+    ))
+    .init_asset::<Mesh>()
+    .insert_resource(SelectedMap(Some(recording.map.clone())))
+    // time never advances on its own, we step the fixed schedules by hand
+    .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::ZERO))
+    .insert_resource(Time::<Fixed>::from_hz(FIXED_TIMESTEP_HZ))
+    .add_systems(FixedUpdate, movement_replay.in_set(FixedSet::Main));
+    configure_fixed_sets(&mut app);
+
+    app.world_mut()
+        .resource_mut::<NextState<GameState>>()
+        .set(GameState::Started { paused: false });
+    app.finish();
+    app.cleanup();
+    // runs the startup schedules and waits for the map to load and spawn
+    let started = Instant::now();
+    loop {
+        app.update();
+        let world = app.world_mut();
+        if world.query::<&MapRoot>().iter(world).next().is_some() {
+            break;
+        }
+        if started.elapsed() > MAP_LOAD_TIMEOUT {
+            return Err(format!("could not load map {:?}", recording.map));
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    app.world_mut().resource_mut::<MapTick>().0 = recording.map_tick;
+    let body = spawn_body(&mut app, ClientId::Local(0), &recording.initial_state);
+    let other_bodies: Vec<Entity> = recording
+        .other_players
+        .iter()
+        .map(|player| spawn_body(&mut app, player.player_id, &player.initial_state))
+        .collect();
+
+    for recorded in &recording.ticks {
+        *app.world_mut()
+            .get_mut::<ActionState<PlayerActions>>(body)
+            .unwrap() = recorded.action_state.clone();
+        for (other_body, action_state) in other_bodies.iter().zip(&recorded.other_action_states) {
+            *app.world_mut()
+                .get_mut::<ActionState<PlayerActions>>(*other_body)
+                .unwrap() = action_state.clone();
+        }
+        app.world_mut().resource_mut::<MapTick>().0 = recorded.tick;
+        app.world_mut().run_schedule(FixedMain);
+
+        let replayed = app.world().get::<Position>(body).unwrap().0;
+        if replayed != recorded.position {
+            return Ok(Some(ReplayDivergence {
+                tick: recorded.tick,
+                recorded: recorded.position,
+                replayed,
+            }));
+        }
+    }
+    Ok(None)
+}
+
+fn spawn_body(app: &mut App, player_id: ClientId, initial: &PlayerSnapshot) -> Entity {
+    app.world_mut()
+        .spawn(PhysicalPlayerServerBodyBundle::new(player_id))
+        .insert((
+            Transform::from_translation(initial.position).with_rotation(initial.rotation),
+            Position(initial.position),
+            Rotation(initial.rotation),
+            LinearVelocity(initial.linear_velocity),
+            PhysicalPlayerBodyMarker {
+                head_entity: None,
+                yaw: initial.yaw,
+            },
+            initial.controller,
+            initial.movement_state,
+            initial.jump_timers,
+        ))
+        .with_children(|commands| {
+            commands
+                .spawn(PhysicalPlayerHeadBundle::new(player_id))
+                .insert(PhysicalPlayerHeadMarker {
+                    pitch: initial.pitch,
+                });
+        })
+        .id()
+}
+
+fn movement_replay(
+    mut player_head_query: Query<
+        (&mut Transform, &mut PhysicalPlayerHeadMarker),
+        Without<PhysicalPlayerBodyMarker>,
+    >,
+    mut player_body_query: Query<
+        (CharacterQuery, &ActionState<PlayerActions>, &Children),
+        (With<PlayerId>, Without<PhysicalPlayerHeadMarker>),
+    >,
+) {
+    for (mut character, action_state, children) in &mut player_body_query {
+        let Some(head_entity) = children
+            .iter()
+            .find(|entity| player_head_query.contains(**entity))
+        else {
+            continue;
+        };
+        let (mut head_transform, mut head) = player_head_query.get_mut(*head_entity).unwrap();
+
+        shared_movement(&mut character, &mut head_transform, &mut head, action_state);
+    }
+}
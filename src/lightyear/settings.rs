@@ -0,0 +1,366 @@
+use std::{
+    fmt, fs,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use clap::{error::ErrorKind, CommandFactory, Parser, ValueEnum};
+use lightyear::prelude::Key;
+use serde::{Deserialize, Serialize};
+
+use crate::map::{available_maps, SpawnPolicy, DEFAULT_MAP};
+
+use super::{
+    conditioner::ConditionerPreset,
+    lib::{AUTH_PORT, CLIENT_ADDR, NETCODE_PORT},
+    my_shared::{lobby::GameMode, physics::CharacterControllerMode},
+};
+
+/// Command line flags. Every flag can also be set through its environment variable,
+/// anything left unset falls back to the config file and then to the defaults.
+#[derive(Parser, Debug, Default)]
+#[command(about)]
+pub struct NetArgs {
+    /// IP of the server that clients connect to
+    #[arg(long, env = "REPRO_SERVER_IP")]
+    pub server_ip: Option<IpAddr>,
+    /// UDP port the server listens on and clients connect to
+    #[arg(long, env = "REPRO_PORT")]
+    pub port: Option<u16>,
+    /// Local address the client binds its socket to
+    #[arg(long, env = "REPRO_CLIENT_ADDR")]
+    pub client_addr: Option<SocketAddr>,
+    /// Id the client authenticates with: `random`, `persistent` or a fixed number
+    #[arg(long, env = "REPRO_CLIENT_ID")]
+    pub client_id: Option<ClientIdSetting>,
+    /// How clients authenticate with the server
+    #[arg(long, env = "REPRO_AUTH")]
+    pub auth: Option<AuthMode>,
+    /// TCP port of the auth service that hands out connect tokens, or checks client ids without them
+    #[arg(long, env = "REPRO_AUTH_PORT")]
+    pub auth_port: Option<u16>,
+    /// Netcode protocol id, must match between clients and server
+    #[arg(long, env = "REPRO_PROTOCOL_ID")]
+    pub protocol_id: Option<u64>,
+    /// Address clients on other machines reach the server at, put in the connect tokens it hands out
+    #[arg(long, env = "REPRO_PUBLIC_ADDR")]
+    pub public_addr: Option<SocketAddr>,
+    /// Key connect tokens are signed with, as 64 hex characters
+    #[arg(long, env = "REPRO_PRIVATE_KEY")]
+    pub private_key: Option<PrivateKey>,
+    /// Link conditioner to start with, on both the client and the server
+    #[arg(long, env = "REPRO_CONDITIONER")]
+    pub conditioner: Option<ConditionerPreset>,
+    /// How the physics of our own player are resolved
+    #[arg(long, env = "REPRO_CONTROLLER")]
+    pub controller: Option<CharacterControllerMode>,
+    /// Map the server plays on, by its file name in `assets/maps` without `.map.ron`
+    #[arg(long, env = "REPRO_MAP")]
+    pub map: Option<String>,
+    /// How the server picks where players spawn
+    #[arg(long, env = "REPRO_SPAWN_POLICY")]
+    pub spawn_policy: Option<SpawnPolicy>,
+    /// The game mode the lobby is set to
+    #[arg(long, env = "REPRO_MODE")]
+    pub mode: Option<GameMode>,
+    /// Seconds the server keeps the body of a disconnected player for it to reconnect to
+    #[arg(long, env = "REPRO_DISCONNECT_GRACE_SECS")]
+    pub disconnect_grace_secs: Option<f32>,
+    /// Name the server announces itself with on the LAN
+    #[arg(long, env = "REPRO_SERVER_NAME")]
+    pub server_name: Option<String>,
+    /// Whether servers announce themselves on the LAN and the main menu lists them
+    #[arg(long, env = "REPRO_LAN_DISCOVERY")]
+    pub lan_discovery: Option<bool>,
+    /// Kick clients once they sent this many invalid inputs, never if unset
+    #[arg(long, env = "REPRO_KICK_AFTER_INVALID_INPUTS")]
+    pub kick_after_invalid_inputs: Option<u32>,
+    /// RON file with the fallback settings
+    #[arg(long, env = "REPRO_CONFIG", default_value = "net_config.ron")]
+    pub config: PathBuf,
+}
+
+/// The network settings used when hosting, joining or running the dedicated server.
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetSettings {
+    pub server_ip: IpAddr,
+    pub port: u16,
+    pub client_addr: SocketAddr,
+    pub client_id: ClientIdSetting,
+    pub auth: AuthMode,
+    pub auth_port: u16,
+    pub protocol_id: u64,
+    /// Only needed for token authentication, the server's own address is only right for local clients.
+    pub public_addr: Option<SocketAddr>,
+    /// `None` keeps the default all-zero key, which every client knows.
+    pub private_key: Option<PrivateKey>,
+    pub conditioner: ConditionerPreset,
+    pub controller: CharacterControllerMode,
+    /// Only used when hosting or running the dedicated server, joining clients get told the map.
+    pub map: String,
+    pub spawn_policy: SpawnPolicy,
+    pub mode: GameMode,
+    pub disconnect_grace_secs: f32,
+    pub server_name: String,
+    pub lan_discovery: bool,
+    /// `None` only counts invalid inputs, without ever kicking anyone.
+    pub kick_after_invalid_inputs: Option<u32>,
+}
+
+impl Default for NetSettings {
+    fn default() -> Self {
+        Self {
+            server_ip: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: NETCODE_PORT,
+            client_addr: CLIENT_ADDR,
+            client_id: ClientIdSetting::Random,
+            auth: AuthMode::Manual,
+            auth_port: AUTH_PORT,
+            protocol_id: 0,
+            public_addr: None,
+            private_key: None,
+            conditioner: ConditionerPreset::Off,
+            controller: CharacterControllerMode::Dynamic,
+            map: DEFAULT_MAP.to_string(),
+            spawn_policy: SpawnPolicy::RoundRobin,
+            mode: GameMode::FreeRoam,
+            disconnect_grace_secs: 10.0,
+            server_name: "Minimal repro server".to_string(),
+            lan_discovery: true,
+            kick_after_invalid_inputs: None,
+        }
+    }
+}
+
+impl NetSettings {
+    /// Parses the command line and environment, filling the gaps from the config file.
+    ///
+    /// Exits with the usage error, as for an invalid flag, if the config file can't be parsed.
+    pub fn from_args() -> Self {
+        let args = NetArgs::parse();
+        match Self::load(&args.config) {
+            Ok(file) => Self::merge(args, file),
+            Err(err) => NetArgs::command()
+                .error(ErrorKind::InvalidValue, err)
+                .exit(),
+        }
+    }
+
+    /// The address clients connect to.
+    pub fn server_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.server_ip, self.port)
+    }
+
+    /// The address the server listens on, reachable from other machines.
+    pub fn bind_addr(&self) -> SocketAddr {
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), self.port)
+    }
+
+    /// The address the auth service listens on.
+    pub fn auth_bind_addr(&self) -> SocketAddr {
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), self.auth_port)
+    }
+
+    pub fn disconnect_grace(&self) -> Duration {
+        Duration::from_secs_f32(self.disconnect_grace_secs.max(0.0))
+    }
+
+    /// The key a client using manual authentication signs its own connect token with.
+    pub fn client_key(&self) -> Key {
+        self.private_key.map(|key| key.0).unwrap_or_default()
+    }
+
+    /// The key the server checks connect tokens with, and the auth service signs them with.
+    ///
+    /// Only manual authentication falls back to the all-zero key every client knows,
+    /// token authentication with it would still let anyone in.
+    pub fn server_key(&self) -> Result<Key, String> {
+        match (self.auth, self.private_key) {
+            (_, Some(key)) => Ok(key.0),
+            (AuthMode::Manual, None) => Ok(Key::default()),
+            (AuthMode::Token, None) => {
+                Err("token authentication needs a secret --private-key".to_string())
+            }
+        }
+    }
+
+    /// The server address put in connect tokens, which clients on any machine connect to.
+    pub fn token_server_addr(&self) -> Result<SocketAddr, String> {
+        self.public_addr.ok_or_else(|| {
+            "token authentication needs the --public-addr clients reach the server at".to_string()
+        })
+    }
+
+    /// Whether these settings are enough to run a server with, the reason if not.
+    pub fn check_server(&self) -> Result<(), String> {
+        self.server_key()?;
+        if self.auth == AuthMode::Token {
+            self.token_server_addr()?;
+        }
+        let maps = available_maps();
+        if !maps.contains(&self.map) {
+            return Err(format!(
+                "there is no map {:?}, pick one of {}",
+                self.map,
+                maps.join(", ")
+            ));
+        }
+        Ok(())
+    }
+
+    /// The settings of the config file, the defaults if there is none.
+    fn load(path: &Path) -> Result<Self, String> {
+        let Ok(contents) = fs::read_to_string(path) else {
+            return Ok(Self::default());
+        };
+        ron::from_str(&contents).map_err(|err| format!("invalid config file {:?}: {}", path, err))
+    }
+
+    /// The flags that were given, the config file for the others.
+    fn merge(args: NetArgs, file: Self) -> Self {
+        Self {
+            server_ip: args.server_ip.unwrap_or(file.server_ip),
+            port: args.port.unwrap_or(file.port),
+            client_addr: args.client_addr.unwrap_or(file.client_addr),
+            client_id: args.client_id.unwrap_or(file.client_id),
+            auth: args.auth.unwrap_or(file.auth),
+            auth_port: args.auth_port.unwrap_or(file.auth_port),
+            protocol_id: args.protocol_id.unwrap_or(file.protocol_id),
+            public_addr: args.public_addr.or(file.public_addr),
+            private_key: args.private_key.or(file.private_key),
+            conditioner: args.conditioner.unwrap_or(file.conditioner),
+            controller: args.controller.unwrap_or(file.controller),
+            map: args.map.unwrap_or(file.map),
+            spawn_policy: args.spawn_policy.unwrap_or(file.spawn_policy),
+            mode: args.mode.unwrap_or(file.mode),
+            disconnect_grace_secs: args
+                .disconnect_grace_secs
+                .unwrap_or(file.disconnect_grace_secs),
+            server_name: args.server_name.unwrap_or(file.server_name),
+            lan_discovery: args.lan_discovery.unwrap_or(file.lan_discovery),
+            kick_after_invalid_inputs: args
+                .kick_after_invalid_inputs
+                .or(file.kick_after_invalid_inputs),
+        }
+    }
+}
+
+/// File holding the id of this install, for [`ClientIdSetting::Persistent`].
+const CLIENT_ID_FILE: &str = "client_id";
+
+/// How a joining client picks the id it authenticates with.
+///
+/// Netcode ignores connection requests for an id that is already connected,
+/// so two clients using the same fixed id can never both join. The server's auth
+/// service tells the second one before it tries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ClientIdSetting {
+    /// A new random id every time we join.
+    #[default]
+    Random,
+    /// A random id generated once and then reused from [`CLIENT_ID_FILE`].
+    Persistent,
+    /// Always this id, for scripted setups where every client gets its own.
+    Fixed(u64),
+}
+
+impl ClientIdSetting {
+    pub fn resolve(&self) -> u64 {
+        match self {
+            ClientIdSetting::Random => rand::random(),
+            ClientIdSetting::Persistent => persistent_client_id(),
+            ClientIdSetting::Fixed(id) => *id,
+        }
+    }
+}
+
+impl FromStr for ClientIdSetting {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "random" => Ok(ClientIdSetting::Random),
+            "persistent" => Ok(ClientIdSetting::Persistent),
+            id => id
+                .parse()
+                .map(ClientIdSetting::Fixed)
+                .map_err(|_| format!("expected `random`, `persistent` or a number, got `{}`", id)),
+        }
+    }
+}
+
+impl fmt::Display for ClientIdSetting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientIdSetting::Random => write!(f, "random"),
+            ClientIdSetting::Persistent => write!(f, "persistent"),
+            ClientIdSetting::Fixed(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+fn persistent_client_id() -> u64 {
+    if let Some(id) = fs::read_to_string(CLIENT_ID_FILE)
+        .ok()
+        .and_then(|contents| contents.trim().parse().ok())
+    {
+        return id;
+    }
+
+    let id = rand::random();
+    if let Err(err) = fs::write(CLIENT_ID_FILE, id.to_string()) {
+        warn!(
+            "Could not store client id in {:?}, the next join uses another one: {}",
+            CLIENT_ID_FILE, err
+        );
+    }
+    id
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ValueEnum)]
+pub enum AuthMode {
+    /// The client builds its own connect token, so it needs to know the private key.
+    #[default]
+    Manual,
+    /// The client fetches a connect token from the server's auth service.
+    Token,
+}
+
+/// A netcode private key, written as 64 hex characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct PrivateKey(pub Key);
+
+impl FromStr for PrivateKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut key = Key::default();
+        if s.len() != key.len() * 2 || !s.is_ascii() {
+            return Err(format!("expected {} hex characters", key.len() * 2));
+        }
+        for (byte, hex) in key.iter_mut().zip(s.as_bytes().chunks(2)) {
+            let hex = std::str::from_utf8(hex).map_err(|err| err.to_string())?;
+            *byte = u8::from_str_radix(hex, 16).map_err(|err| err.to_string())?;
+        }
+        Ok(Self(key))
+    }
+}
+
+impl TryFrom<String> for PrivateKey {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<PrivateKey> for String {
+    fn from(key: PrivateKey) -> Self {
+        key.0.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+}
@@ -1,50 +1,37 @@
-use avian3d::{
-    prelude::*,
-    sync::{SyncConfig, SyncPlugin},
-    PhysicsPlugins,
-};
+use avian3d::prelude::*;
 use bevy::{
-    color::palettes::css,
     prelude::*,
     window::{CursorGrabMode, PrimaryWindow},
 };
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
-use lightyear::MyLightyearPlugin;
-use my_states::{GameState, InGame, InGamePaused, InGameUnpaused, MyStatesPlugin};
-use my_ui::MyUiPlugin;
-
-pub const FIXED_TIMESTEP_HZ: f64 = 64.0;
-
-mod lightyear;
-pub mod my_states;
-mod my_ui;
+use minimal_repro_lightyear_rollbacks::{
+    lightyear::{settings::NetSettings, MyLightyearPlugin},
+    map::MyMapPlugin,
+    my_states::{GameState, InGame, InGamePaused, InGameUnpaused, MyStatesPlugin},
+    my_ui::MyUiPlugin,
+    MyPhysicsPlugin,
+};
 
 fn main() {
     let mut app = App::new();
 
-    app.add_plugins((
-        DefaultPlugins,
-        WorldInspectorPlugin::default(),
-        PhysicsDebugPlugin::new(FixedUpdate),
-        PhysicsPlugins::new(FixedUpdate)
-            .build()
-            .disable::<SyncPlugin>()
-            .disable::<SleepingPlugin>(),
-        SyncPlugin::new(PostUpdate),
-        MyLightyearPlugin,
-        MyStatesPlugin,
-        MyUiPlugin,
-    ))
-    .insert_resource(SyncConfig {
-        transform_to_position: false,
-        position_to_transform: true,
-    })
-    .insert_resource(Time::new_with(Physics::fixed_once_hz(FIXED_TIMESTEP_HZ)))
-    .add_systems(Startup, setup_camera)
-    .add_systems(OnEnter(InGamePaused), ungrab_mouse)
-    .add_systems(OnEnter(InGameUnpaused), grab_mouse)
-    .add_systems(OnEnter(InGame), spawn_map)
-    .add_systems(Update, ((pause_unpause_game,).run_if(in_state(InGame)),));
+    app.insert_resource(NetSettings::from_args())
+        .add_plugins((
+            DefaultPlugins,
+            WorldInspectorPlugin::default(),
+            PhysicsDebugPlugin::new(FixedUpdate),
+            MyPhysicsPlugin,
+            MyLightyearPlugin,
+            MyStatesPlugin,
+            MyUiPlugin,
+            MyMapPlugin,
+        ))
+        .add_systems(Startup, setup_camera)
+        .add_systems(OnEnter(InGamePaused), ungrab_mouse)
+        // losing the connection leaves the game without pausing it first
+        .add_systems(OnExit(InGame), ungrab_mouse)
+        .add_systems(OnEnter(InGameUnpaused), grab_mouse)
+        .add_systems(Update, ((pause_unpause_game,).run_if(in_state(InGame)),));
 
     app.run();
 }
@@ -60,69 +47,12 @@ fn setup_camera(mut commands: Commands) {
                 .looking_at(Vec3::new(0.0, 10.0, 0.0), Vec3::Y),
             ..Default::default()
         },
-        StateScoped(InGame),
+        // kept when going back to the main menu, which needs a camera too
         IsDefaultUiCamera,
         My3DCamera,
     ));
 }
 
-fn spawn_map(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-) {
-    commands.spawn((
-        Name::new("Point light"),
-        PointLightBundle {
-            transform: Transform::from_xyz(5.0, 5.0, 5.0),
-            ..default()
-        },
-        StateScoped(InGame),
-    ));
-
-    // A directly-down light to tell where the player is going to land.
-    commands.spawn((
-        Name::new("Directional light"),
-        DirectionalLightBundle {
-            directional_light: DirectionalLight {
-                illuminance: 4000.0,
-                shadows_enabled: true,
-                ..Default::default()
-            },
-            transform: Transform::default().looking_at(-Vec3::Y, Vec3::Z),
-            ..Default::default()
-        },
-        StateScoped(InGame),
-    ));
-
-    // Spawn the ground.
-    commands.spawn((
-        Name::new("Ground"),
-        PbrBundle {
-            mesh: meshes.add(Plane3d::default().mesh().size(128.0, 128.0)),
-            material: materials.add(Color::WHITE),
-            ..Default::default()
-        },
-        RigidBody::Static,
-        Collider::half_space(Vec3::Y),
-        StateScoped(InGame),
-    ));
-
-    // Spawn a little platform for the player to jump on.
-    commands.spawn((
-        Name::new("Platform"),
-        PbrBundle {
-            mesh: meshes.add(Cuboid::new(4.0, 1.0, 4.0)),
-            material: materials.add(Color::from(css::GRAY)),
-            transform: Transform::from_xyz(-6.0, 2.0, 0.0),
-            ..Default::default()
-        },
-        RigidBody::Static,
-        Collider::cuboid(4.0, 1.0, 4.0),
-        StateScoped(InGame),
-    ));
-}
-
 fn ungrab_mouse(mut primary_window_query: Query<&mut Window, With<PrimaryWindow>>) {
     let Ok(mut window) = primary_window_query.get_single_mut() else {
         return;
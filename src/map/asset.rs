@@ -0,0 +1,184 @@
+use std::fmt;
+
+use avian3d::{
+    math::{Scalar, Vector},
+    prelude::*,
+};
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+};
+use serde::Deserialize;
+
+use super::platforms::MovingPlatform;
+
+/// Everything a map is made of, loaded from a `.map.ron` file in `assets/maps`.
+///
+/// Only the colliders, platforms, spawn points and kill volumes matter to the simulation,
+/// lights and colors are skipped where nothing is rendered.
+#[derive(Asset, TypePath, Deserialize, Debug)]
+pub struct Map {
+    #[serde(default)]
+    pub lights: Vec<MapLight>,
+    #[serde(default)]
+    pub objects: Vec<MapObject>,
+    #[serde(default)]
+    pub platforms: Vec<MapPlatform>,
+    #[serde(default)]
+    pub spawn_points: Vec<MapSpawnPoint>,
+    #[serde(default)]
+    pub kill_volumes: Vec<MapKillVolume>,
+    #[serde(default)]
+    pub bounds: MapBounds,
+}
+
+#[derive(Deserialize, Debug)]
+pub enum MapLight {
+    Point {
+        position: Vector,
+    },
+    /// Shines from above, looking straight down.
+    Directional {
+        illuminance: f32,
+        #[serde(default)]
+        shadows: bool,
+    },
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub enum MapShape {
+    /// An infinite floor facing up.
+    HalfSpace,
+    Cuboid {
+        size: Vector,
+    },
+    Cylinder {
+        radius: Scalar,
+        height: Scalar,
+    },
+}
+
+impl MapShape {
+    pub fn collider(&self) -> Collider {
+        match *self {
+            MapShape::HalfSpace => Collider::half_space(Vector::Y),
+            MapShape::Cuboid { size } => Collider::cuboid(size.x, size.y, size.z),
+            MapShape::Cylinder { radius, height } => Collider::cylinder(radius, height),
+        }
+    }
+
+    pub fn mesh(&self) -> Mesh {
+        match *self {
+            MapShape::HalfSpace => Plane3d::default().mesh().size(128.0, 128.0).into(),
+            MapShape::Cuboid { size } => Cuboid::from_size(size).into(),
+            MapShape::Cylinder { radius, height } => Cylinder::new(radius, height).into(),
+        }
+    }
+}
+
+/// A static collider.
+#[derive(Deserialize, Debug)]
+pub struct MapObject {
+    pub name: String,
+    #[serde(default)]
+    pub position: Vector,
+    pub shape: MapShape,
+    #[serde(default = "default_color")]
+    pub color: (f32, f32, f32),
+}
+
+#[derive(Deserialize, Debug)]
+pub struct MapPlatform {
+    pub name: String,
+    pub shape: MapShape,
+    pub motion: MovingPlatform,
+    #[serde(default = "default_color")]
+    pub color: (f32, f32, f32),
+}
+
+#[derive(Deserialize, Debug)]
+pub struct MapSpawnPoint {
+    pub position: Vector,
+    /// Which way players spawned here look, in radians.
+    #[serde(default)]
+    pub yaw: Scalar,
+}
+
+/// Anyone touching it is killed and respawned, or teleported to `teleport_to` if set.
+#[derive(Deserialize, Debug)]
+pub struct MapKillVolume {
+    pub position: Vector,
+    pub size: Vector,
+    #[serde(default)]
+    pub teleport_to: Option<Vector>,
+}
+
+/// The box players have to stay in, anyone leaving it is respawned.
+#[derive(Component, Deserialize, Debug, Clone, Copy)]
+pub struct MapBounds {
+    pub min: Vector,
+    pub max: Vector,
+}
+
+impl Default for MapBounds {
+    fn default() -> Self {
+        Self {
+            min: Vector::new(-64.0, -50.0, -64.0),
+            max: Vector::new(64.0, 200.0, 64.0),
+        }
+    }
+}
+
+impl MapBounds {
+    pub fn contains(&self, position: Vector) -> bool {
+        position.cmpge(self.min).all() && position.cmple(self.max).all()
+    }
+}
+
+fn default_color() -> (f32, f32, f32) {
+    (1.0, 1.0, 1.0)
+}
+
+#[derive(Default)]
+pub struct MapLoader;
+
+#[derive(Debug)]
+pub enum MapLoaderError {
+    Io(std::io::Error),
+    Ron(ron::error::SpannedError),
+}
+
+impl fmt::Display for MapLoaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapLoaderError::Io(err) => write!(f, "could not read map: {}", err),
+            MapLoaderError::Ron(err) => write!(f, "invalid map: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for MapLoaderError {}
+
+impl AssetLoader for MapLoader {
+    type Asset = Map;
+    type Settings = ();
+    type Error = MapLoaderError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Map, MapLoaderError> {
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .await
+            .map_err(MapLoaderError::Io)?;
+        ron::de::from_bytes(&bytes).map_err(MapLoaderError::Ron)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["map.ron"]
+    }
+}
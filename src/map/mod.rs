@@ -0,0 +1,256 @@
+use std::fs;
+
+use avian3d::{math::Vector, prelude::*};
+use bevy::{
+    asset::{io::file::FileAssetReader, LoadState},
+    prelude::*,
+};
+use lightyear::prelude::Tick;
+
+use crate::{lightyear::my_shared::lib::FixedSet, my_states::InGame};
+
+pub use asset::{Map, MapBounds, MapLight, MapLoader, MapShape};
+pub use platforms::{MapTick, MovingPlatform};
+pub use spawn::{SpawnPoint, SpawnPolicy};
+
+mod asset;
+mod platforms;
+mod spawn;
+
+/// The map hosts start with when nothing else is picked.
+pub const DEFAULT_MAP: &str = "default";
+
+/// Folder in the assets the maps are loaded from.
+const MAPS_FOLDER: &str = "maps";
+
+pub struct MyMapPlugin;
+
+impl Plugin for MyMapPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<Map>()
+            .init_asset_loader::<MapLoader>()
+            .init_resource::<MapTick>()
+            .init_resource::<SelectedMap>()
+            .init_resource::<LoadedMap>()
+            .add_event::<MapLoadFailed>()
+            .add_systems(
+                Update,
+                (
+                    load_selected_map.run_if(resource_changed::<SelectedMap>),
+                    check_map_load,
+                    spawn_map.run_if(in_state(InGame)),
+                )
+                    .chain(),
+            )
+            .add_systems(
+                FixedUpdate,
+                platforms::move_platforms.in_set(FixedSet::Main),
+            );
+    }
+}
+
+/// The name of the map to build once we are in game, `None` until the server told us.
+///
+/// The server sets it from its settings when it starts, clients get it sent on connecting,
+/// so every peer builds the same collision world.
+#[derive(Resource, Default, Debug, Clone, PartialEq, Eq)]
+pub struct SelectedMap(pub Option<String>);
+
+#[derive(Resource, Default)]
+struct LoadedMap(Option<Handle<Map>>);
+
+/// The selected map could not be loaded, so it never gets built.
+#[derive(Event, Debug, Clone)]
+pub struct MapLoadFailed {
+    pub name: String,
+    pub error: String,
+}
+
+/// Marks the entity standing for the map that is currently built.
+#[derive(Component, Debug)]
+pub struct MapRoot;
+
+/// A sensor volume that kills whoever enters it, or teleports them to `teleport_to`.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct KillVolume {
+    pub teleport_to: Option<Vector>,
+}
+
+/// The names of all the maps in the assets folder, sorted.
+pub fn available_maps() -> Vec<String> {
+    let Ok(entries) = fs::read_dir(FileAssetReader::get_base_path().join(MAPS_FOLDER)) else {
+        return vec![DEFAULT_MAP.to_string()];
+    };
+    let mut maps: Vec<String> = entries
+        .filter_map(|entry| {
+            let file_name = entry.ok()?.file_name();
+            let name = file_name.to_str()?.strip_suffix(".map.ron")?;
+            Some(name.to_string())
+        })
+        .collect();
+    maps.sort();
+    maps
+}
+
+fn load_selected_map(
+    selected_map: Res<SelectedMap>,
+    mut loaded_map: ResMut<LoadedMap>,
+    asset_server: Res<AssetServer>,
+) {
+    loaded_map.0 = selected_map.0.as_ref().map(|name| {
+        info!("Loading map {:?}", name);
+        asset_server.load(format!("{}/{}.map.ron", MAPS_FOLDER, name))
+    });
+}
+
+fn check_map_load(
+    selected_map: Res<SelectedMap>,
+    mut loaded_map: ResMut<LoadedMap>,
+    asset_server: Res<AssetServer>,
+    mut events: EventWriter<MapLoadFailed>,
+) {
+    let (Some(name), Some(handle)) = (&selected_map.0, &loaded_map.0) else {
+        return;
+    };
+    let LoadState::Failed(err) = asset_server.load_state(handle) else {
+        return;
+    };
+    let error = err.to_string();
+    error!("Could not load map {:?}: {}", name, error);
+    events.send(MapLoadFailed {
+        name: name.clone(),
+        error,
+    });
+    // reported once, until another map is selected
+    loaded_map.0 = None;
+}
+
+/// Spawns the map colliders, plus lights and meshes when rendering is available.
+///
+/// The dedicated server runs without a renderer, so there are no materials and only
+/// the collision world gets spawned.
+fn spawn_map(
+    mut commands: Commands,
+    selected_map: Res<SelectedMap>,
+    loaded_map: Res<LoadedMap>,
+    maps: Res<Assets<Map>>,
+    root_query: Query<(), With<MapRoot>>,
+    meshes: Option<ResMut<Assets<Mesh>>>,
+    materials: Option<ResMut<Assets<StandardMaterial>>>,
+) {
+    if !root_query.is_empty() {
+        return;
+    }
+    let (Some(name), Some(handle)) = (&selected_map.0, &loaded_map.0) else {
+        return;
+    };
+    let Some(map) = maps.get(handle) else {
+        return;
+    };
+
+    info!("Spawning map {:?}", name);
+    commands.spawn((
+        Name::new(format!("Map {}", name)),
+        MapRoot,
+        map.bounds,
+        StateScoped(InGame),
+    ));
+
+    let mut render_assets = meshes.zip(materials);
+
+    if render_assets.is_some() {
+        for light in &map.lights {
+            match *light {
+                MapLight::Point { position } => commands.spawn((
+                    Name::new("Point light"),
+                    PointLightBundle {
+                        transform: Transform::from_translation(position),
+                        ..default()
+                    },
+                    StateScoped(InGame),
+                )),
+                MapLight::Directional {
+                    illuminance,
+                    shadows,
+                } => commands.spawn((
+                    Name::new("Directional light"),
+                    DirectionalLightBundle {
+                        directional_light: DirectionalLight {
+                            illuminance,
+                            shadows_enabled: shadows,
+                            ..default()
+                        },
+                        transform: Transform::default().looking_at(-Vec3::Y, Vec3::Z),
+                        ..default()
+                    },
+                    StateScoped(InGame),
+                )),
+            };
+        }
+    }
+
+    for object in &map.objects {
+        let mut entity = commands.spawn((
+            Name::new(object.name.clone()),
+            SpatialBundle::from_transform(Transform::from_translation(object.position)),
+            RigidBody::Static,
+            object.shape.collider(),
+            StateScoped(InGame),
+        ));
+        if let Some((meshes, materials)) = render_assets.as_mut() {
+            entity.insert((
+                meshes.add(object.shape.mesh()),
+                materials.add(color(object.color)),
+            ));
+        }
+    }
+
+    for platform in &map.platforms {
+        let (position, rotation) = platform.motion.pose(Tick(0));
+        let mut entity = commands.spawn((
+            Name::new(platform.name.clone()),
+            SpatialBundle::from_transform(
+                Transform::from_translation(position).with_rotation(rotation),
+            ),
+            RigidBody::Kinematic,
+            platform.shape.collider(),
+            platform.motion,
+            StateScoped(InGame),
+        ));
+        if let Some((meshes, materials)) = render_assets.as_mut() {
+            entity.insert((
+                meshes.add(platform.shape.mesh()),
+                materials.add(color(platform.color)),
+            ));
+        }
+    }
+
+    for spawn_point in &map.spawn_points {
+        commands.spawn((
+            Name::new("Spawn point"),
+            SpatialBundle::from_transform(Transform::from_translation(spawn_point.position)),
+            SpawnPoint {
+                yaw: spawn_point.yaw,
+            },
+            StateScoped(InGame),
+        ));
+    }
+
+    for kill_volume in &map.kill_volumes {
+        commands.spawn((
+            Name::new("Kill volume"),
+            SpatialBundle::from_transform(Transform::from_translation(kill_volume.position)),
+            RigidBody::Static,
+            Collider::cuboid(kill_volume.size.x, kill_volume.size.y, kill_volume.size.z),
+            Sensor,
+            KillVolume {
+                teleport_to: kill_volume.teleport_to,
+            },
+            StateScoped(InGame),
+        ));
+    }
+}
+
+fn color((red, green, blue): (f32, f32, f32)) -> Color {
+    Color::srgb(red, green, blue)
+}
@@ -0,0 +1,91 @@
+use std::f32::consts::TAU;
+
+use avian3d::{
+    math::{Quaternion, Scalar, Vector},
+    prelude::*,
+};
+use bevy::prelude::*;
+use lightyear::prelude::Tick;
+use serde::{Deserialize, Serialize};
+
+/// The tick the moving platforms are posed for.
+///
+/// Kept up to date by the networking side with the tick being simulated, which is an older one
+/// while a client replays a rollback, so platforms are always where they were on that tick.
+#[derive(Resource, Default, Debug, Clone, Copy)]
+pub struct MapTick(pub Tick);
+
+/// A kinematic platform whose pose is a pure function of the tick.
+///
+/// Periods should be powers of two, so the motion stays continuous when the `u16` tick wraps.
+#[derive(Component, Serialize, Deserialize, Debug, Clone, Copy)]
+pub enum MovingPlatform {
+    /// Eases back and forth between two points.
+    Linear {
+        from: Vector,
+        to: Vector,
+        period_ticks: u16,
+    },
+    /// Spins around the vertical axis through `center`.
+    Rotating { center: Vector, ticks_per_turn: u16 },
+}
+
+impl MovingPlatform {
+    pub fn pose(&self, tick: Tick) -> (Vector, Quaternion) {
+        match *self {
+            MovingPlatform::Linear {
+                from,
+                to,
+                period_ticks,
+            } => {
+                let phase = phase(tick, period_ticks);
+                let t = 0.5 - 0.5 * (TAU * phase).cos();
+                (from.lerp(to, t), Quaternion::IDENTITY)
+            }
+            MovingPlatform::Rotating {
+                center,
+                ticks_per_turn,
+            } => (
+                center,
+                Quaternion::from_rotation_y(TAU * phase(tick, ticks_per_turn)),
+            ),
+        }
+    }
+}
+
+/// How far into its period the tick is, from 0 to 1.
+fn phase(tick: Tick, period_ticks: u16) -> Scalar {
+    let period_ticks = period_ticks.max(1);
+    (tick.0 % period_ticks) as Scalar / period_ticks as Scalar
+}
+
+/// Puts platforms where they are at the start of the tick, with the velocity that takes them
+/// to where they are at the start of the next one.
+pub(super) fn move_platforms(
+    map_tick: Res<MapTick>,
+    time: Res<Time<Fixed>>,
+    mut query: Query<(
+        &MovingPlatform,
+        &mut Position,
+        &mut Rotation,
+        &mut LinearVelocity,
+        &mut AngularVelocity,
+    )>,
+) {
+    let delta_time = time.timestep().as_secs_f32();
+    let tick = map_tick.0;
+    let next_tick = Tick(tick.0.wrapping_add(1));
+
+    for (platform, mut position, mut rotation, mut linear_velocity, mut angular_velocity) in
+        &mut query
+    {
+        let (current_position, current_rotation) = platform.pose(tick);
+        let (next_position, next_rotation) = platform.pose(next_tick);
+
+        position.0 = current_position;
+        rotation.0 = current_rotation;
+        linear_velocity.0 = (next_position - current_position) / delta_time;
+        angular_velocity.0 =
+            (next_rotation * current_rotation.inverse()).to_scaled_axis() / delta_time;
+    }
+}
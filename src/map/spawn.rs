@@ -0,0 +1,62 @@
+use avian3d::math::{Scalar, Vector};
+use bevy::prelude::*;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// Where players can be spawned, looking in the direction of `yaw`.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct SpawnPoint {
+    pub yaw: Scalar,
+}
+
+/// Used when the map has no spawn points at all.
+const FALLBACK_SPAWN: (Vector, Scalar) = (Vector::new(0.0, 5.0, 0.0), 0.0);
+
+/// How the server picks the spawn point of a player that (re)spawns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ValueEnum)]
+pub enum SpawnPolicy {
+    /// Each spawn goes to the next spawn point in the map.
+    #[default]
+    RoundRobin,
+    /// The spawn point farthest away from the closest other player.
+    Farthest,
+    Random,
+}
+
+impl SpawnPolicy {
+    /// Picks a position and yaw among `spawn_points`.
+    ///
+    /// `next` is the round robin counter, kept by the caller from one spawn to the next.
+    pub fn pick(
+        &self,
+        spawn_points: &[(Vector, Scalar)],
+        enemies: &[Vector],
+        next: &mut usize,
+    ) -> (Vector, Scalar) {
+        if spawn_points.is_empty() {
+            return FALLBACK_SPAWN;
+        }
+
+        match self {
+            SpawnPolicy::RoundRobin => {
+                let spawn_point = spawn_points[*next % spawn_points.len()];
+                *next = next.wrapping_add(1);
+                spawn_point
+            }
+            SpawnPolicy::Farthest => {
+                let closest_enemy = |position: Vector| {
+                    enemies
+                        .iter()
+                        .map(|enemy| enemy.distance_squared(position))
+                        .fold(Scalar::INFINITY, Scalar::min)
+                };
+                spawn_points
+                    .iter()
+                    .copied()
+                    .max_by(|(a, _), (b, _)| closest_enemy(*a).total_cmp(&closest_enemy(*b)))
+                    .unwrap_or(FALLBACK_SPAWN)
+            }
+            SpawnPolicy::Random => spawn_points[rand::random::<usize>() % spawn_points.len()],
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use bevy::prelude::*;
 
-pub(crate) struct MyStatesPlugin;
+pub struct MyStatesPlugin;
 
 impl Plugin for MyStatesPlugin {
     fn build(&self, app: &mut App) {
@@ -23,6 +23,8 @@ impl Plugin for MyStatesPlugin {
 pub enum GameState {
     #[default]
     MainMenu,
+    /// Connected and waiting for the host to start the match.
+    Lobby,
     Started {
         paused: bool,
     },
@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+use bevy::{color::palettes::css, prelude::*};
+
+use crate::{
+    lightyear::my_shared::chat::{ChatInput, ChatLog},
+    my_states::InGame,
+};
+
+/// How many chat lines are shown at once.
+const VISIBLE_LINES: usize = 8;
+
+/// Lines older than this are hidden while the chat box is closed.
+const LINE_LIFETIME: Duration = Duration::from_secs(10);
+
+/// Draws the chat in the bottom left corner while in game.
+pub(crate) struct ChatUiPlugin;
+
+impl Plugin for ChatUiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(InGame), spawn_chat_box)
+            .add_systems(Update, update_chat_box.run_if(in_state(InGame)));
+    }
+}
+
+#[derive(Component)]
+struct ChatBoxText;
+
+fn spawn_chat_box(mut commands: Commands) {
+    commands.spawn((
+        Name::new("ChatBox"),
+        TextBundle::from_sections([
+            TextSection::new(
+                "",
+                TextStyle {
+                    font_size: 18.0,
+                    color: css::WHITE.into(),
+                    ..default()
+                },
+            ),
+            TextSection::new(
+                "",
+                TextStyle {
+                    font_size: 18.0,
+                    color: css::YELLOW.into(),
+                    ..default()
+                },
+            ),
+        ])
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(10.0),
+            left: Val::Px(10.0),
+            max_width: Val::Px(500.0),
+            ..default()
+        }),
+        StateScoped(InGame),
+        ChatBoxText,
+    ));
+}
+
+fn update_chat_box(
+    time: Res<Time>,
+    chat_log: Res<ChatLog>,
+    chat_input: Res<ChatInput>,
+    mut text_query: Query<&mut Text, With<ChatBoxText>>,
+) {
+    let now = time.elapsed();
+    let lines: Vec<String> = chat_log
+        .0
+        .iter()
+        .rev()
+        .take(VISIBLE_LINES)
+        .filter(|line| chat_input.open || now - line.received < LINE_LIFETIME)
+        .map(|line| format!("{:?}: {}", line.sender.0, line.text))
+        .collect();
+    let log = lines.into_iter().rev().collect::<Vec<_>>().join("\n");
+    let draft = if chat_input.open {
+        format!("\n> {}_", chat_input.draft)
+    } else {
+        String::new()
+    };
+
+    for mut text in &mut text_query {
+        if text.sections[0].value != log {
+            text.sections[0].value = log.clone();
+        }
+        if text.sections[1].value != draft {
+            text.sections[1].value = draft.clone();
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts};
+use lightyear::prelude::{
+    client::{ClientCommands, NetworkingState as ClientNetworkingState},
+    server::NetworkingState as ServerNetworkingState,
+};
+
+use crate::lightyear::{
+    conditioner::{ConditionerPreset, ConditionerValues, LinkConditioners},
+    lib::PendingReconnect,
+};
+
+/// An F2 panel to pick the simulated network conditions without recompiling.
+pub(crate) struct ConditionerPanelPlugin;
+
+impl Plugin for ConditionerPanelPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ConditionerPanelOpen>().add_systems(
+            Update,
+            (
+                toggle_conditioner_panel,
+                conditioner_panel.run_if(|open: Res<ConditionerPanelOpen>| open.0),
+            )
+                .chain(),
+        );
+    }
+}
+
+#[derive(Resource, Default)]
+struct ConditionerPanelOpen(bool);
+
+fn toggle_conditioner_panel(
+    input: Res<ButtonInput<KeyCode>>,
+    mut open: ResMut<ConditionerPanelOpen>,
+) {
+    if input.just_pressed(KeyCode::F2) {
+        open.0 = !open.0;
+    }
+}
+
+// Lightyear wraps the conditioner into the transport's receiver when it opens it and has no way
+// to swap it afterwards, so changes only reach the next connection: the client can rebuild its
+// connection to apply them, the server has to be hosted again.
+fn conditioner_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut conditioners: ResMut<LinkConditioners>,
+    client_state: Res<State<ClientNetworkingState>>,
+    server_state: Option<Res<State<ServerNetworkingState>>>,
+) {
+    egui::Window::new("Link conditioner").show(contexts.ctx_mut(), |ui| {
+        ui.heading("Client");
+        conditioner_controls(ui, "client", &mut conditioners.client);
+        let hosting =
+            server_state.is_some_and(|state| *state.get() == ServerNetworkingState::Started);
+        match client_state.get() {
+            ClientNetworkingState::Disconnected => {
+                ui.label("Applied when joining a server");
+            }
+            ClientNetworkingState::Connected if !hosting => {
+                ui.label("Not live, the connection has to be rebuilt");
+                if ui.button("Reconnect to apply").clicked() {
+                    commands.disconnect_client();
+                    commands.insert_resource(PendingReconnect);
+                }
+            }
+            _ => {
+                ui.label("Can't change while connected, applied the next time you join a server");
+            }
+        }
+
+        ui.separator();
+        ui.heading("Server");
+        conditioner_controls(ui, "server", &mut conditioners.server);
+        if hosting {
+            ui.label("Can't change while hosting, applied the next time you host");
+        } else {
+            ui.label("Applied when hosting");
+        }
+    });
+}
+
+fn conditioner_controls(ui: &mut egui::Ui, id: &str, values: &mut ConditionerValues) {
+    ui.push_id(id, |ui| {
+        ui.horizontal(|ui| {
+            for preset in ConditionerPreset::ALL {
+                if ui.button(preset.label()).clicked() {
+                    *values = preset.values();
+                }
+            }
+        });
+        ui.add(egui::Slider::new(&mut values.latency_ms, 0..=1000).text("latency (ms)"));
+        ui.add(egui::Slider::new(&mut values.jitter_ms, 0..=500).text("jitter (ms)"));
+        ui.add(egui::Slider::new(&mut values.loss, 0.0..=0.5).text("packet loss"));
+    });
+}
@@ -0,0 +1,198 @@
+use bevy::{color::palettes::css, prelude::*};
+use lightyear::prelude::{
+    client::{ClientConnection, NetClient},
+    ClientConnectionManager,
+};
+
+use crate::{
+    lightyear::my_shared::{
+        lib::Channel1,
+        lobby::{Lobby, SetReady, StartMatch},
+    },
+    my_states::GameState,
+};
+
+use super::ButtonPressedTrigger;
+
+/// Shows who is in the lobby, lets everyone ready up and the host start the match.
+pub(crate) struct LobbyUiPlugin;
+
+impl Plugin for LobbyUiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::Lobby), setup_lobby_ui)
+            .add_systems(
+                Update,
+                update_lobby_ui
+                    .run_if(in_state(GameState::Lobby).and_then(resource_exists::<Lobby>)),
+            );
+    }
+}
+
+#[derive(Component)]
+struct LobbyText;
+
+#[derive(Component)]
+struct ReadyButtonText;
+
+#[derive(Component)]
+struct StartMatchButton;
+
+fn update_lobby_ui(
+    lobby: Res<Lobby>,
+    connection: Res<ClientConnection>,
+    mut text_query: Query<&mut Text, (With<LobbyText>, Without<ReadyButtonText>)>,
+    mut ready_text_query: Query<&mut Text, (With<ReadyButtonText>, Without<LobbyText>)>,
+    mut start_button_query: Query<&mut Visibility, With<StartMatchButton>>,
+) {
+    if !lobby.is_changed() {
+        return;
+    }
+    let own_id = connection.client.id();
+
+    let mut description = format!("Map: {}\nMode: {:?}\n", lobby.map, lobby.mode);
+    for player in &lobby.players {
+        description.push_str(&format!(
+            "\n{:?}{}{}",
+            player.client_id,
+            if lobby.host == Some(player.client_id) {
+                " (host)"
+            } else {
+                ""
+            },
+            if player.ready { " - ready" } else { "" },
+        ));
+    }
+    if lobby.start_tick.is_some() {
+        description.push_str("\n\nStarting...");
+    }
+    for mut text in &mut text_query {
+        text.sections[0].value = description.clone();
+    }
+
+    let ready = lobby
+        .players
+        .iter()
+        .any(|player| player.client_id == own_id && player.ready);
+    for mut text in &mut ready_text_query {
+        text.sections[0].value = if ready { "Not ready" } else { "Ready" }.to_string();
+    }
+
+    let can_start = lobby.host == Some(own_id) && lobby.everyone_ready();
+    for mut visibility in &mut start_button_query {
+        visibility.set_if_neq(if can_start {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        });
+    }
+}
+
+fn toggle_ready(
+    _: Trigger<ButtonPressedTrigger>,
+    lobby: Option<Res<Lobby>>,
+    connection: Res<ClientConnection>,
+    mut connection_manager: ResMut<ClientConnectionManager>,
+) {
+    let own_id = connection.client.id();
+    let ready = lobby.is_some_and(|lobby| {
+        lobby
+            .players
+            .iter()
+            .any(|player| player.client_id == own_id && player.ready)
+    });
+    let _ = connection_manager.send_message::<Channel1, _>(&mut SetReady(!ready));
+}
+
+fn start_match(
+    _: Trigger<ButtonPressedTrigger>,
+    mut connection_manager: ResMut<ClientConnectionManager>,
+) {
+    let _ = connection_manager.send_message::<Channel1, _>(&mut StartMatch);
+}
+
+fn lobby_button() -> ButtonBundle {
+    ButtonBundle {
+        style: Style {
+            width: Val::Px(200.0),
+            height: Val::Px(65.0),
+            border: UiRect::all(Val::Px(5.0)),
+            // horizontally center child text
+            justify_content: JustifyContent::Center,
+            // vertically center child text
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        border_color: BorderColor(Color::BLACK),
+        background_color: css::GRAY.into(),
+        ..default()
+    }
+}
+
+fn button_text(text: &str) -> TextBundle {
+    TextBundle::from_section(
+        text,
+        TextStyle {
+            font_size: 30.0,
+            color: css::WHITE.into(),
+            ..default()
+        },
+    )
+}
+
+fn setup_lobby_ui(mut commands: Commands) {
+    commands
+        .spawn((
+            StateScoped(GameState::Lobby),
+            Name::new("LobbyUiContainer"),
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    row_gap: Val::Px(10.0),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    flex_direction: FlexDirection::Column,
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .with_children(|commands| {
+            commands.spawn((
+                Name::new("LobbyText"),
+                TextBundle::from_section(
+                    "Waiting for the server...",
+                    TextStyle {
+                        font_size: 24.0,
+                        color: css::WHITE.into(),
+                        ..default()
+                    },
+                ),
+                LobbyText,
+            ));
+
+            commands
+                .spawn(lobby_button())
+                .observe(toggle_ready)
+                .with_children(|commands| {
+                    commands.spawn((button_text("Ready"), ReadyButtonText));
+                });
+
+            commands
+                .spawn((lobby_button(), StartMatchButton))
+                .insert(Visibility::Hidden)
+                .observe(start_match)
+                .with_children(|commands| {
+                    commands.spawn(button_text("Start match"));
+                });
+
+            commands.spawn(TextBundle::from_section(
+                "Press Q to leave",
+                TextStyle {
+                    font_size: 20.0,
+                    color: css::GRAY.into(),
+                    ..default()
+                },
+            ));
+        });
+}
@@ -1,17 +1,48 @@
 use bevy::{color::palettes::css, prelude::*};
-use lightyear::prelude::{client::ClientCommands, server::ServerCommands};
+use chat::ChatUiPlugin;
+use conditioner_panel::ConditionerPanelPlugin;
+use lightyear::prelude::server::ServerCommands;
+use lobby::LobbyUiPlugin;
+use net_stats_hud::NetStatsHudPlugin;
+use server_browser::{spawn_server_browser, ServerAddressInput, ServerBrowserPlugin};
 
-use crate::{lightyear::lib::MyNetConfigControl, my_states::GameState};
+use crate::{
+    lightyear::{
+        lib::{ConnectionError, MyNetConfigControl},
+        settings::NetSettings,
+    },
+    map::available_maps,
+    my_states::GameState,
+};
+
+mod chat;
+mod conditioner_panel;
+mod lobby;
+mod net_stats_hud;
+mod server_browser;
 
 pub struct MyUiPlugin;
 
 impl Plugin for MyUiPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(OnEnter(GameState::MainMenu), setup_ui)
-            .add_systems(
-                Update,
-                ui_interaction_system.run_if(in_state(GameState::MainMenu)),
-            );
+        app.add_plugins((
+            ConditionerPanelPlugin,
+            LobbyUiPlugin,
+            ServerBrowserPlugin,
+            ChatUiPlugin,
+            NetStatsHudPlugin,
+        ))
+        .add_systems(OnEnter(GameState::MainMenu), setup_ui)
+        .add_systems(
+            Update,
+            ui_interaction_system
+                .run_if(in_state(GameState::MainMenu).or_else(in_state(GameState::Lobby))),
+        )
+        .add_systems(
+            Update,
+            (update_connection_error_text, update_map_button_text)
+                .run_if(in_state(GameState::MainMenu)),
+        );
     }
 }
 
@@ -42,6 +73,48 @@ fn ui_interaction_system(
 #[derive(Event)]
 struct ButtonPressedTrigger;
 
+#[derive(Component)]
+struct ConnectionErrorText;
+
+fn update_connection_error_text(
+    connection_error: Res<ConnectionError>,
+    mut text_query: Query<&mut Text, With<ConnectionErrorText>>,
+) {
+    let message = connection_error.0.as_deref().unwrap_or_default();
+    for mut text in &mut text_query {
+        if text.sections[0].value != message {
+            text.sections[0].value = message.to_string();
+        }
+    }
+}
+
+#[derive(Component)]
+struct MapButtonText;
+
+fn update_map_button_text(
+    settings: Res<NetSettings>,
+    mut text_query: Query<&mut Text, With<MapButtonText>>,
+) {
+    let label = format!("Map: {}", settings.map);
+    for mut text in &mut text_query {
+        if text.sections[0].value != label {
+            text.sections[0].value = label.clone();
+        }
+    }
+}
+
+/// Picks the next map in the assets folder for hosting.
+fn cycle_map(settings: &mut NetSettings) {
+    let maps = available_maps();
+    let next = maps
+        .iter()
+        .position(|map| *map == settings.map)
+        .map_or(0, |index| (index + 1) % maps.len());
+    if let Some(map) = maps.get(next) {
+        settings.map = map.clone();
+    }
+}
+
 fn setup_ui(mut commands: Commands) {
     commands
         .spawn((
@@ -61,6 +134,41 @@ fn setup_ui(mut commands: Commands) {
             },
         ))
         .with_children(|commands| {
+            commands
+                .spawn((ButtonBundle {
+                    style: Style {
+                        width: Val::Px(250.0),
+                        height: Val::Px(45.0),
+                        border: UiRect::all(Val::Px(5.0)),
+                        // horizontally center child text
+                        justify_content: JustifyContent::Center,
+                        // vertically center child text
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    border_color: BorderColor(Color::BLACK),
+                    background_color: css::GRAY.into(),
+                    ..default()
+                },))
+                .observe(
+                    |_: Trigger<ButtonPressedTrigger>, mut settings: ResMut<NetSettings>| {
+                        cycle_map(&mut settings);
+                    },
+                )
+                .with_children(|commands| {
+                    commands.spawn((
+                        TextBundle::from_section(
+                            "",
+                            TextStyle {
+                                font_size: 24.0,
+                                color: css::WHITE.into(),
+                                ..default()
+                            },
+                        ),
+                        MapButtonText,
+                    ));
+                });
+
             commands
                 .spawn((ButtonBundle {
                     style: Style {
@@ -81,8 +189,9 @@ fn setup_ui(mut commands: Commands) {
                     |_: Trigger<ButtonPressedTrigger>,
                      mut commands: Commands,
                      mut network: MyNetConfigControl| {
-                        network.set_to_host();
-                        commands.start_server();
+                        if network.set_to_host() {
+                            commands.start_server();
+                        }
                         //commands.connect_client();
                     },
                 )
@@ -115,10 +224,9 @@ fn setup_ui(mut commands: Commands) {
                 },))
                 .observe(
                     |_: Trigger<ButtonPressedTrigger>,
-                     mut commands: Commands,
-                     mut network: MyNetConfigControl| {
-                        network.set_to_join();
-                        commands.connect_client();
+                     mut network: MyNetConfigControl,
+                     address: Res<ServerAddressInput>| {
+                        network.set_to_join_address(&address.0);
                     },
                 )
                 .with_children(|commands| {
@@ -131,5 +239,20 @@ fn setup_ui(mut commands: Commands) {
                         },
                     ),));
                 });
+
+            spawn_server_browser(commands);
+
+            commands.spawn((
+                Name::new("ConnectionErrorText"),
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 20.0,
+                        color: css::RED.into(),
+                        ..default()
+                    },
+                ),
+                ConnectionErrorText,
+            ));
         });
 }
@@ -0,0 +1,172 @@
+use bevy::{color::palettes::css, prelude::*};
+
+use crate::{
+    lightyear::net_stats::{NetMetric, NetStats, ServerNetStats, GRAPH_SAMPLES},
+    my_states::InGame,
+};
+
+/// Height of each metric's graph.
+const GRAPH_HEIGHT: f32 = 24.0;
+
+/// An F3 overlay with the numbers of our connection and a rolling graph for each.
+pub(crate) struct NetStatsHudPlugin;
+
+impl Plugin for NetStatsHudPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NetStatsHudVisible>()
+            .add_systems(OnEnter(InGame), spawn_hud)
+            .add_systems(
+                Update,
+                (toggle_hud, update_hud.run_if(in_state(InGame))).chain(),
+            );
+    }
+}
+
+#[derive(Resource, Default)]
+struct NetStatsHudVisible(bool);
+
+#[derive(Component)]
+struct NetStatsHud;
+
+#[derive(Component)]
+struct MetricText(NetMetric);
+
+/// One bar of a metric's graph, `0` being the oldest sample.
+#[derive(Component)]
+struct GraphBar {
+    metric: NetMetric,
+    index: usize,
+}
+
+#[derive(Component)]
+struct ServerStatsText;
+
+fn toggle_hud(input: Res<ButtonInput<KeyCode>>, mut visible: ResMut<NetStatsHudVisible>) {
+    if input.just_pressed(KeyCode::F3) {
+        visible.0 = !visible.0;
+    }
+}
+
+fn spawn_hud(mut commands: Commands) {
+    let text_style = TextStyle {
+        font_size: 16.0,
+        color: css::LIGHT_GREEN.into(),
+        ..default()
+    };
+
+    commands
+        .spawn((
+            Name::new("NetStatsHud"),
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(10.0),
+                    right: Val::Px(10.0),
+                    row_gap: Val::Px(4.0),
+                    flex_direction: FlexDirection::Column,
+                    ..default()
+                },
+                background_color: Color::BLACK.with_alpha(0.5).into(),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            StateScoped(InGame),
+            NetStatsHud,
+        ))
+        .with_children(|commands| {
+            for metric in NetMetric::ALL {
+                commands.spawn((
+                    TextBundle::from_section("", text_style.clone()),
+                    MetricText(metric),
+                ));
+                commands
+                    .spawn(NodeBundle {
+                        style: Style {
+                            width: Val::Px(2.0 * GRAPH_SAMPLES as f32),
+                            height: Val::Px(GRAPH_HEIGHT),
+                            align_items: AlignItems::FlexEnd,
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|commands| {
+                        for index in 0..GRAPH_SAMPLES {
+                            commands.spawn((
+                                NodeBundle {
+                                    style: Style {
+                                        width: Val::Px(2.0),
+                                        height: Val::Px(0.0),
+                                        ..default()
+                                    },
+                                    background_color: css::LIGHT_GREEN.into(),
+                                    ..default()
+                                },
+                                GraphBar { metric, index },
+                            ));
+                        }
+                    });
+            }
+            commands.spawn((TextBundle::from_section("", text_style), ServerStatsText));
+        });
+}
+
+fn update_hud(
+    stats: Res<NetStats>,
+    server_stats: Res<ServerNetStats>,
+    visible: Res<NetStatsHudVisible>,
+    mut hud_query: Query<&mut Visibility, With<NetStatsHud>>,
+    mut text_query: Query<(&mut Text, &MetricText), Without<ServerStatsText>>,
+    mut server_text_query: Query<&mut Text, (With<ServerStatsText>, Without<MetricText>)>,
+    mut bar_query: Query<(&mut Style, &GraphBar)>,
+) {
+    for mut visibility in &mut hud_query {
+        visibility.set_if_neq(if visible.0 {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        });
+    }
+    if !visible.0 || !stats.is_changed() {
+        return;
+    }
+
+    let latest = stats.latest();
+    for (mut text, MetricText(metric)) in &mut text_query {
+        text.sections[0].value = metric.format(&latest);
+    }
+
+    for metric in NetMetric::ALL {
+        let values: Vec<f32> = stats.0.iter().map(|sample| metric.value(sample)).collect();
+        let low = values.iter().copied().fold(0.0, f32::min);
+        let high = values.iter().copied().fold(low + 1.0, f32::max);
+        // the newest sample is always drawn at the right edge
+        let offset = GRAPH_SAMPLES - values.len();
+        for (mut style, bar) in &mut bar_query {
+            if bar.metric != metric {
+                continue;
+            }
+            let height = bar
+                .index
+                .checked_sub(offset)
+                .map_or(0.0, |i| (values[i] - low) / (high - low));
+            style.height = Val::Px(height * GRAPH_HEIGHT);
+        }
+    }
+
+    let clients: Vec<String> = server_stats
+        .0
+        .iter()
+        .map(|client| {
+            format!(
+                "{:?}: RTT {:.0} ms, jitter {:.1} ms, {} invalid inputs",
+                client.client_id,
+                client.rtt.as_secs_f32() * 1000.0,
+                client.jitter.as_secs_f32() * 1000.0,
+                client.rejected_inputs
+            )
+        })
+        .collect();
+    for mut text in &mut server_text_query {
+        text.sections[0].value = clients.join("\n");
+    }
+}
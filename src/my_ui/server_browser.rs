@@ -0,0 +1,220 @@
+use bevy::{
+    color::palettes::css,
+    input::{
+        keyboard::{Key, KeyboardInput},
+        ButtonState,
+    },
+    prelude::*,
+};
+
+use crate::{
+    lightyear::{discovery::DiscoveredServers, lib::MyNetConfigControl, settings::NetSettings},
+    my_states::GameState,
+};
+
+use super::ButtonPressedTrigger;
+
+/// The manual address field and the list of servers found on the LAN, in the main menu.
+pub(crate) struct ServerBrowserPlugin;
+
+impl Plugin for ServerBrowserPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ServerAddressInput>()
+            .add_systems(OnEnter(GameState::MainMenu), reset_server_address)
+            .add_systems(
+                Update,
+                (
+                    type_server_address,
+                    update_server_address_text,
+                    update_server_list,
+                )
+                    .run_if(in_state(GameState::MainMenu)),
+            );
+    }
+}
+
+/// What is typed in the address field, joined by the Connect button or Enter.
+#[derive(Resource, Default, Debug)]
+pub(super) struct ServerAddressInput(pub(super) String);
+
+#[derive(Component)]
+struct ServerAddressText;
+
+#[derive(Component)]
+struct ServerList;
+
+fn reset_server_address(settings: Res<NetSettings>, mut address: ResMut<ServerAddressInput>) {
+    address.0 = settings.server_addr().to_string();
+}
+
+// The main menu has no other text input, so typing always goes to the address field.
+fn type_server_address(
+    mut events: EventReader<KeyboardInput>,
+    mut address: ResMut<ServerAddressInput>,
+    mut network: MyNetConfigControl,
+) {
+    for event in events.read() {
+        if event.state != ButtonState::Pressed {
+            continue;
+        }
+        match &event.logical_key {
+            Key::Character(characters) => address.0.extend(
+                characters
+                    .chars()
+                    .filter(|c| c.is_ascii_alphanumeric() || ".:[]".contains(*c)),
+            ),
+            Key::Backspace => {
+                address.0.pop();
+            }
+            Key::Enter => network.set_to_join_address(&address.0),
+            _ => {}
+        }
+    }
+}
+
+fn update_server_address_text(
+    address: Res<ServerAddressInput>,
+    mut text_query: Query<&mut Text, With<ServerAddressText>>,
+) {
+    for mut text in &mut text_query {
+        if text.sections[0].value != address.0 {
+            text.sections[0].value = address.0.clone();
+        }
+    }
+}
+
+fn update_server_list(
+    mut commands: Commands,
+    servers: Res<DiscoveredServers>,
+    list_query: Query<Entity, With<ServerList>>,
+    new_list_query: Query<(), Added<ServerList>>,
+) {
+    if !servers.is_changed() && new_list_query.is_empty() {
+        return;
+    }
+
+    for list in &list_query {
+        commands.entity(list).despawn_descendants();
+        commands.entity(list).with_children(|commands| {
+            if servers.0.is_empty() {
+                commands.spawn(TextBundle::from_section(
+                    "No servers found on the LAN",
+                    TextStyle {
+                        font_size: 20.0,
+                        color: css::GRAY.into(),
+                        ..default()
+                    },
+                ));
+            }
+
+            for server in &servers.0 {
+                let addr = server.addr;
+                commands
+                    .spawn(browser_button(Val::Px(400.0)))
+                    .observe(
+                        move |_: Trigger<ButtonPressedTrigger>, mut network: MyNetConfigControl| {
+                            network.set_to_join(addr);
+                        },
+                    )
+                    .with_children(|commands| {
+                        commands.spawn(TextBundle::from_section(
+                            format!(
+                                "{} - {} - {} players ({})",
+                                server.beacon.name, server.beacon.map, server.beacon.players, addr
+                            ),
+                            TextStyle {
+                                font_size: 20.0,
+                                color: css::WHITE.into(),
+                                ..default()
+                            },
+                        ));
+                    });
+            }
+        });
+    }
+}
+
+fn browser_button(width: Val) -> ButtonBundle {
+    ButtonBundle {
+        style: Style {
+            width,
+            height: Val::Px(40.0),
+            border: UiRect::all(Val::Px(5.0)),
+            // horizontally center child text
+            justify_content: JustifyContent::Center,
+            // vertically center child text
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        border_color: BorderColor(Color::BLACK),
+        background_color: css::GRAY.into(),
+        ..default()
+    }
+}
+
+/// The address field, the refresh button and the list of discovered servers.
+pub(super) fn spawn_server_browser(commands: &mut ChildBuilder) {
+    commands
+        .spawn((
+            Name::new("ServerAddressField"),
+            NodeBundle {
+                style: Style {
+                    width: Val::Px(250.0),
+                    height: Val::Px(40.0),
+                    border: UiRect::all(Val::Px(2.0)),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                border_color: BorderColor(Color::WHITE),
+                background_color: Color::BLACK.into(),
+                ..default()
+            },
+        ))
+        .with_children(|commands| {
+            commands.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 24.0,
+                        color: css::WHITE.into(),
+                        ..default()
+                    },
+                ),
+                ServerAddressText,
+            ));
+        });
+
+    commands
+        .spawn(browser_button(Val::Px(250.0)))
+        .observe(
+            |_: Trigger<ButtonPressedTrigger>, mut servers: ResMut<DiscoveredServers>| {
+                // running servers announce themselves again within a second
+                servers.0.clear();
+            },
+        )
+        .with_children(|commands| {
+            commands.spawn(TextBundle::from_section(
+                "Refresh LAN servers",
+                TextStyle {
+                    font_size: 24.0,
+                    color: css::WHITE.into(),
+                    ..default()
+                },
+            ));
+        });
+
+    commands.spawn((
+        Name::new("ServerList"),
+        NodeBundle {
+            style: Style {
+                row_gap: Val::Px(5.0),
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::Column,
+                ..default()
+            },
+            ..default()
+        },
+        ServerList,
+    ));
+}
@@ -0,0 +1,47 @@
+mod harness;
+
+use harness::Stepper;
+
+/// Enough ticks for a message to reach the server and come back.
+const ROUND_TRIP_TICKS: usize = 32;
+
+#[test]
+fn chat_message_reaches_every_client_with_its_sender() {
+    let mut stepper = Stepper::new(2);
+    stepper.connect();
+    let sender = stepper.clients[0].0;
+
+    stepper.send_chat(0, "  hello there  ");
+    stepper.frame_step_n(ROUND_TRIP_TICKS);
+
+    for client in 0..2 {
+        assert_eq!(
+            stepper.chat_log(client),
+            vec![(sender, "hello there".to_string())]
+        );
+    }
+}
+
+#[test]
+fn server_drops_invalid_and_flooding_chat_messages() {
+    let mut stepper = Stepper::new(1);
+    stepper.connect();
+
+    stepper.send_chat(0, "   ");
+    stepper.send_chat(0, &"a".repeat(1000));
+    for i in 0..10 {
+        stepper.send_chat(0, &format!("message {i}"));
+    }
+    stepper.frame_step_n(ROUND_TRIP_TICKS);
+
+    let texts: Vec<String> = stepper
+        .chat_log(0)
+        .into_iter()
+        .map(|(_, text)| text)
+        .collect();
+    assert_eq!(
+        texts,
+        (0..5).map(|i| format!("message {i}")).collect::<Vec<_>>(),
+        "the server should pass on only the first messages within the rate limit"
+    );
+}
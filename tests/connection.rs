@@ -0,0 +1,56 @@
+mod harness;
+
+use bevy::prelude::*;
+use harness::{ScriptedInput, Stepper};
+
+/// Enough ticks for the disconnect to reach the server.
+const DISCONNECT_TICKS: usize = 64;
+
+#[test]
+fn disconnected_player_is_despawned() {
+    let mut stepper = Stepper::new(2);
+    stepper.set_disconnect_grace(0.0);
+    stepper.connect();
+
+    stepper.disconnect(0);
+    stepper.frame_step_n(DISCONNECT_TICKS);
+
+    assert!(
+        !stepper.server_has_player(0),
+        "the server kept the body of the disconnected client"
+    );
+    assert!(stepper.server_has_player(1));
+}
+
+#[test]
+fn reconnected_player_resumes_its_body() {
+    let mut stepper = Stepper::new(1);
+    stepper.set_disconnect_grace(10.0);
+    stepper.connect();
+
+    stepper.set_input(
+        0,
+        ScriptedInput {
+            movement: Vec2::Y,
+            ..default()
+        },
+    );
+    stepper.frame_step_n(32);
+    stepper.set_input(0, ScriptedInput::default());
+    let body = stepper.server_body(0);
+
+    stepper.disconnect(0);
+    stepper.frame_step_n(DISCONNECT_TICKS);
+    assert_eq!(
+        stepper.server_body(0),
+        body,
+        "the server did not keep the body during the grace period"
+    );
+
+    stepper.reconnect(0, 42);
+    assert_eq!(
+        stepper.server_body(0),
+        body,
+        "the client got a new body instead of its old one"
+    );
+}
@@ -0,0 +1,453 @@
+//! Runs a dedicated server and any number of joining clients in the same process.
+//!
+//! The peers talk over crossbeam channels instead of UDP sockets, and time only moves
+//! when [`Stepper::frame_step`] is called, so every test sees the same sequence of ticks.
+
+// each test file uses its own part of the harness
+#![allow(dead_code)]
+
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::PathBuf,
+    thread,
+    time::Duration,
+};
+
+use avian3d::{math::Vector, prelude::*};
+use bevy::{
+    input::InputPlugin, prelude::*, state::app::StatesPlugin, time::TimeUpdateStrategy,
+    utils::Instant,
+};
+use leafwing_input_manager::{
+    plugin::InputManagerSystem,
+    prelude::{ActionState, InputMap},
+};
+use lightyear::prelude::{
+    client::{self, Authentication, ClientCommands, Predicted},
+    server::{self, ServerCommands},
+    ClientConnectionManager, ClientId, Key, Message, Mode,
+};
+use minimal_repro_lightyear_rollbacks::{
+    lightyear::{
+        lib::{ConnectionError, NETCODE_PORT},
+        my_shared::{
+            chat::{ChatLog, ChatMessage},
+            lib::{Channel1, PhysicalPlayerBodyMarker, PlayerActions, PlayerId, Respawn},
+            lobby::{Lobby, SetReady, StartMatch},
+            physics::MovementState,
+            recording::ToggleRecording,
+            shared_config,
+        },
+        net_stats::ServerNetStats,
+        settings::NetSettings,
+        MyDedicatedServerPlugin, MyLightyearPlugin,
+    },
+    map::MyMapPlugin,
+    my_states::{InGame, MyStatesPlugin},
+    MyPhysicsPlugin, FIXED_TIMESTEP_HZ,
+};
+
+/// How long clients get to connect, sync, load the map and spawn their player.
+const CONNECT_TIMEOUT_FRAMES: usize = 10 * FIXED_TIMESTEP_HZ as usize;
+
+/// Real time given to the asset loader between frames while connecting.
+const LOAD_WAIT: Duration = Duration::from_millis(1);
+
+/// How far the predicted player may end up from the server's once both came to rest.
+pub const TOLERANCE: f32 = 0.05;
+
+/// Enough ticks for the last inputs to reach the server, get confirmed and for the body to stop.
+pub const SETTLE_TICKS: usize = 256;
+
+/// The inputs a client presses on every frame until they are changed.
+#[derive(Resource, Default, Debug, Clone, Copy)]
+pub struct ScriptedInput {
+    pub movement: Vec2,
+    pub look_around: Vec2,
+    pub jump: bool,
+    pub sprint: bool,
+    pub crouch: bool,
+}
+
+pub struct Stepper {
+    pub server: App,
+    pub clients: Vec<(ClientId, App)>,
+    frame_duration: Duration,
+    current_time: Instant,
+}
+
+impl Stepper {
+    pub fn new(num_clients: usize) -> Self {
+        let frame_duration = Duration::from_secs_f64(1.0 / FIXED_TIMESTEP_HZ);
+        let current_time = Instant::now();
+
+        let mut server_app = App::new();
+        add_common_plugins(&mut server_app);
+        server_app.add_plugins(MyDedicatedServerPlugin);
+
+        let mut channels = Vec::with_capacity(num_clients);
+        let mut clients = Vec::with_capacity(num_clients);
+        for i in 0..num_clients {
+            let client_id = i as u64 + 1;
+            let client_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 1000 + i as u16);
+            let (to_server_send, to_server_recv) = crossbeam_channel::unbounded();
+            let (from_server_send, from_server_recv) = crossbeam_channel::unbounded();
+            channels.push((client_addr, to_server_recv, from_server_send));
+
+            let mut client_app = App::new();
+            add_common_plugins(&mut client_app);
+            client_app
+                .add_plugins((InputPlugin, MyLightyearPlugin))
+                .init_asset::<StandardMaterial>()
+                .init_resource::<ScriptedInput>()
+                .add_systems(
+                    PreUpdate,
+                    apply_scripted_input.in_set(InputManagerSystem::ManualControl),
+                );
+            *client_app
+                .world_mut()
+                .resource_mut::<client::ClientConfig>() = client::ClientConfig {
+                shared: shared_config(Mode::Separate),
+                net: client::NetConfig::Netcode {
+                    auth: Authentication::Manual {
+                        server_addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), NETCODE_PORT),
+                        client_id,
+                        private_key: Key::default(),
+                        protocol_id: 0,
+                    },
+                    config: client::NetcodeConfig::default(),
+                    io: client::IoConfig::from_transport(client::ClientTransport::LocalChannel {
+                        recv: from_server_recv,
+                        send: to_server_send,
+                    }),
+                },
+                ..default()
+            };
+            clients.push((ClientId::Netcode(client_id), client_app));
+        }
+
+        server_app
+            .world_mut()
+            .resource_mut::<server::ServerConfig>()
+            .net = vec![server::NetConfig::Netcode {
+            config: server::NetcodeConfig::default(),
+            io: server::IoConfig::from_transport(server::ServerTransport::Channels { channels }),
+        }];
+
+        let mut stepper = Self {
+            server: server_app,
+            clients,
+            frame_duration,
+            current_time,
+        };
+        for app in stepper.apps() {
+            app.insert_resource(TimeUpdateStrategy::ManualInstant(current_time));
+            app.finish();
+            app.cleanup();
+        }
+        stepper
+    }
+
+    /// Starts the server, connects every client, starts the match from the lobby
+    /// and waits until they all control a predicted player.
+    pub fn connect(&mut self) {
+        self.server.world_mut().commands().start_server();
+        self.server.world_mut().flush();
+        self.frame_step();
+        for (_, app) in &mut self.clients {
+            app.world_mut().commands().connect_client();
+            app.world_mut().flush();
+        }
+
+        self.start_match();
+        self.wait_for_players();
+    }
+
+    /// Readies every client once they are all in the lobby, then has the host start the match.
+    fn start_match(&mut self) {
+        self.wait_for_lobby(|lobby, clients| lobby.players.len() == clients);
+        for client in 0..self.clients.len() {
+            self.send_message(client, SetReady(true));
+        }
+        self.wait_for_lobby(|lobby, _| lobby.everyone_ready());
+
+        let host = self.server.world().resource::<Lobby>().host;
+        let host = self
+            .clients
+            .iter()
+            .position(|(client_id, _)| Some(*client_id) == host)
+            .expect("the lobby has no host");
+        self.send_message(host, StartMatch);
+    }
+
+    fn wait_for_lobby(&mut self, condition: impl Fn(&Lobby, usize) -> bool) {
+        for _ in 0..CONNECT_TIMEOUT_FRAMES {
+            self.frame_step();
+            let lobby = self.server.world().get_resource::<Lobby>();
+            if lobby.is_some_and(|lobby| condition(lobby, self.clients.len())) {
+                return;
+            }
+        }
+        panic!("the lobby did not get ready within {CONNECT_TIMEOUT_FRAMES} frames");
+    }
+
+    fn send_message<M: Message>(&mut self, client: usize, mut message: M) {
+        self.clients[client]
+            .1
+            .world_mut()
+            .resource_mut::<ClientConnectionManager>()
+            .send_message::<Channel1, _>(&mut message)
+            .expect("could not send the message");
+    }
+
+    /// Connects a client again after [`Stepper::disconnect`] and waits until it controls a player.
+    ///
+    /// It joins with a new id, as a client picking a random id on every join does.
+    pub fn reconnect(&mut self, client: usize, client_id: u64) {
+        let (id, app) = &mut self.clients[client];
+        *id = ClientId::Netcode(client_id);
+        let mut config = app.world_mut().resource_mut::<client::ClientConfig>();
+        if let client::NetConfig::Netcode {
+            auth:
+                Authentication::Manual {
+                    client_id: manual_id,
+                    ..
+                },
+            ..
+        } = &mut config.net
+        {
+            *manual_id = client_id;
+        }
+        app.world_mut().commands().connect_client();
+        app.world_mut().flush();
+        self.wait_for_players();
+    }
+
+    fn wait_for_players(&mut self) {
+        for _ in 0..CONNECT_TIMEOUT_FRAMES {
+            // maps load on the asset threads, which do not follow our fake clock
+            thread::sleep(LOAD_WAIT);
+            self.frame_step();
+            if (0..self.clients.len()).all(|i| self.try_predicted_position(i).is_some()) {
+                return;
+            }
+        }
+        panic!("clients did not spawn their players within {CONNECT_TIMEOUT_FRAMES} frames");
+    }
+
+    /// Advances every app by one fixed tick, the server first.
+    pub fn frame_step(&mut self) {
+        self.current_time += self.frame_duration;
+        let current_time = self.current_time;
+        for app in self.apps() {
+            app.insert_resource(TimeUpdateStrategy::ManualInstant(current_time));
+            app.update();
+        }
+    }
+
+    pub fn frame_step_n(&mut self, frames: usize) {
+        for _ in 0..frames {
+            self.frame_step();
+        }
+    }
+
+    pub fn set_input(&mut self, client: usize, input: ScriptedInput) {
+        self.clients[client].1.insert_resource(input);
+    }
+
+    /// Sends a chat message from the client, as when typed into the chat box.
+    pub fn send_chat(&mut self, client: usize, text: &str) {
+        self.send_message(client, ChatMessage(text.to_string()));
+    }
+
+    /// The chat lines the client received, with who sent them.
+    pub fn chat_log(&self, client: usize) -> Vec<(ClientId, String)> {
+        self.clients[client]
+            .1
+            .world()
+            .resource::<ChatLog>()
+            .0
+            .iter()
+            .map(|line| (line.sender.0, line.text.clone()))
+            .collect()
+    }
+
+    /// Sends the [`Respawn`] request the client sends when K is pressed.
+    pub fn request_respawn(&mut self, client: usize) {
+        self.send_message(client, Respawn);
+    }
+
+    /// Starts recording the client's inputs, or stops and saves them to `path`, as F5 does.
+    pub fn toggle_recording(&mut self, client: usize, path: Option<PathBuf>) {
+        self.clients[client]
+            .1
+            .world_mut()
+            .send_event(ToggleRecording { path });
+    }
+
+    /// Disconnects the client from the server, as when the player quits.
+    pub fn disconnect(&mut self, client: usize) {
+        let app = &mut self.clients[client].1;
+        app.world_mut().commands().disconnect_client();
+        app.world_mut().flush();
+    }
+
+    /// Where the server has the player of the given client.
+    pub fn server_position(&mut self, client: usize) -> Vector {
+        self.try_server_position(client)
+            .expect("the server has no body for this client")
+    }
+
+    pub fn server_has_player(&mut self, client: usize) -> bool {
+        self.try_server_position(client).is_some()
+    }
+
+    /// The entity of the given client's player on the server.
+    pub fn server_body(&mut self, client: usize) -> Entity {
+        let client_id = self.clients[client].0;
+        let world = self.server.world_mut();
+        world
+            .query_filtered::<(Entity, &PlayerId), With<PhysicalPlayerBodyMarker>>()
+            .iter(world)
+            .find(|(_, player_id)| player_id.0 == client_id)
+            .map(|(entity, _)| entity)
+            .expect("the server has no body for this client")
+    }
+
+    /// How long the server keeps the players of disconnected clients.
+    pub fn set_disconnect_grace(&mut self, secs: f32) {
+        self.server
+            .world_mut()
+            .resource_mut::<NetSettings>()
+            .disconnect_grace_secs = secs;
+    }
+
+    /// After how many invalid inputs the server kicks a client.
+    pub fn set_kick_after_invalid_inputs(&mut self, count: Option<u32>) {
+        self.server
+            .world_mut()
+            .resource_mut::<NetSettings>()
+            .kick_after_invalid_inputs = count;
+    }
+
+    /// How many inputs of the given client the server rejected, as last sampled for the HUD.
+    pub fn rejected_inputs(&self, client: usize) -> u32 {
+        let client_id = self.clients[client].0;
+        self.server
+            .world()
+            .resource::<ServerNetStats>()
+            .0
+            .iter()
+            .find(|stats| stats.client_id == client_id)
+            .map_or(0, |stats| stats.rejected_inputs)
+    }
+
+    /// Why the client last failed to join or lost its connection, as shown in the main menu.
+    pub fn connection_error(&self, client: usize) -> Option<String> {
+        self.clients[client]
+            .1
+            .world()
+            .resource::<ConnectionError>()
+            .0
+            .clone()
+    }
+
+    fn try_server_position(&mut self, client: usize) -> Option<Vector> {
+        let client_id = self.clients[client].0;
+        let world = self.server.world_mut();
+        world
+            .query_filtered::<(&Position, &PlayerId), With<PhysicalPlayerBodyMarker>>()
+            .iter(world)
+            .find(|(_, player_id)| player_id.0 == client_id)
+            .map(|(position, _)| position.0)
+    }
+
+    /// What the server has the player of the given client doing.
+    pub fn server_movement_state(&mut self, client: usize) -> MovementState {
+        let client_id = self.clients[client].0;
+        let world = self.server.world_mut();
+        world
+            .query::<(&MovementState, &PlayerId)>()
+            .iter(world)
+            .find(|(_, player_id)| player_id.0 == client_id)
+            .map(|(movement_state, _)| *movement_state)
+            .expect("the server has no body for this client")
+    }
+
+    /// Where the client predicts its own player to be.
+    pub fn predicted_position(&mut self, client: usize) -> Vector {
+        self.try_predicted_position(client)
+            .expect("the client has no predicted player")
+    }
+
+    fn try_predicted_position(&mut self, client: usize) -> Option<Vector> {
+        let (client_id, app) = &mut self.clients[client];
+        let world = app.world_mut();
+        if world.get_resource::<State<InGame>>().is_none() {
+            return None;
+        }
+        world
+            .query_filtered::<(&Position, &PlayerId), (With<Predicted>, With<PhysicalPlayerBodyMarker>)>()
+            .iter(world)
+            .find(|(_, player_id)| player_id.0 == *client_id)
+            .map(|(position, _)| position.0)
+    }
+
+    fn apps(&mut self) -> impl Iterator<Item = &mut App> {
+        std::iter::once(&mut self.server).chain(self.clients.iter_mut().map(|(_, app)| app))
+    }
+}
+
+/// Asserts that the client predicts its player where the server has it.
+pub fn assert_converged(stepper: &mut Stepper, client: usize) {
+    let predicted = stepper.predicted_position(client);
+    let server = stepper.server_position(client);
+    assert!(
+        predicted.distance(server) <= TOLERANCE,
+        "client {client} predicted {predicted} but the server has {server}"
+    );
+}
+
+fn add_common_plugins(app: &mut App) {
+    // the peers only talk over channels, nothing should go out on the real network
+    app.insert_resource(NetSettings {
+        lan_discovery: false,
+        // the auth service still listens, on any free port so that tests can run side by side
+        auth_port: 0,
+        ..default()
+    })
+    .add_plugins((
+        MinimalPlugins,
+        StatesPlugin,
+        TransformPlugin,
+        HierarchyPlugin,
+        AssetPlugin::default(),
+        MyPhysicsPlugin,
+        MyStatesPlugin,
+        MyMapPlugin,
+    ))
+    .init_asset::<Mesh>();
+}
+
+// Runs after leafwing has updated the action state from the (empty) keyboard and mouse,
+// and before lightyear buffers it as this tick's input.
+fn apply_scripted_input(
+    input: Res<ScriptedInput>,
+    mut query: Query<&mut ActionState<PlayerActions>, With<InputMap<PlayerActions>>>,
+) {
+    for mut action_state in &mut query {
+        action_state.set_axis_pair(&PlayerActions::Move, input.movement);
+        action_state.set_axis_pair(&PlayerActions::LookAround, input.look_around);
+        for (action, pressed) in [
+            (PlayerActions::Jump, input.jump),
+            (PlayerActions::Sprint, input.sprint),
+            (PlayerActions::Crouch, input.crouch),
+        ] {
+            if pressed {
+                action_state.press(&action);
+            } else {
+                action_state.release(&action);
+            }
+        }
+    }
+}
@@ -0,0 +1,79 @@
+mod harness;
+
+use bevy::prelude::*;
+use harness::{assert_converged, ScriptedInput, Stepper, SETTLE_TICKS};
+
+/// Enough ticks for the kick to go through, its reason to reach the client and the
+/// server to drop the connection.
+const KICK_TICKS: usize = 128;
+
+/// Inputs are sent as changes, so a forged value held still is only in the few messages
+/// around the tick it started on. `step` makes each tick's forged value a new one.
+fn forged_input(step: usize) -> ScriptedInput {
+    ScriptedInput {
+        movement: Vec2::new(10.0 + step as f32, 0.0),
+        look_around: Vec2::new(10_000.0 + step as f32, 0.0),
+        ..default()
+    }
+}
+
+#[test]
+fn forged_inputs_are_counted_and_clamped_everywhere() {
+    let mut stepper = Stepper::new(2);
+    stepper.connect();
+
+    stepper.set_input(0, forged_input(0));
+    stepper.frame_step_n(32);
+    stepper.set_input(0, ScriptedInput::default());
+    stepper.frame_step_n(SETTLE_TICKS);
+
+    assert!(stepper.rejected_inputs(0) > 0);
+    assert_eq!(stepper.rejected_inputs(1), 0);
+    // the client clamps its prediction the same way the server does
+    assert_converged(&mut stepper, 0);
+}
+
+#[test]
+fn keyboard_diagonal_is_not_counted() {
+    let mut stepper = Stepper::new(1);
+    stepper.connect();
+
+    stepper.set_input(
+        0,
+        ScriptedInput {
+            movement: Vec2::new(1.0, 1.0),
+            look_around: Vec2::new(50.0, 10.0),
+            ..default()
+        },
+    );
+    stepper.frame_step_n(64);
+
+    assert_eq!(stepper.rejected_inputs(0), 0);
+}
+
+#[test]
+fn client_sending_forged_inputs_is_kicked() {
+    let mut stepper = Stepper::new(2);
+    stepper.set_disconnect_grace(0.0);
+    stepper.set_kick_after_invalid_inputs(Some(10));
+    stepper.connect();
+
+    for step in 0..KICK_TICKS {
+        stepper.set_input(0, forged_input(step));
+        stepper.frame_step();
+    }
+
+    assert!(
+        !stepper.server_has_player(0),
+        "the server kept the client sending forged inputs"
+    );
+    assert!(stepper.server_has_player(1));
+    let error = stepper
+        .connection_error(0)
+        .expect("the kicked client was not told why");
+    assert!(
+        error.contains("invalid inputs"),
+        "the kicked client was told: {error}"
+    );
+    assert_eq!(stepper.connection_error(1), None);
+}
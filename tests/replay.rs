@@ -0,0 +1,48 @@
+mod harness;
+
+use std::{env, fs};
+
+use bevy::prelude::*;
+use harness::{ScriptedInput, Stepper, SETTLE_TICKS};
+use minimal_repro_lightyear_rollbacks::lightyear::replay::{recording::Recording, replay};
+
+#[test]
+fn recorded_session_replays_without_diverging() {
+    let mut stepper = Stepper::new(2);
+    stepper.connect();
+    let path = env::temp_dir().join(format!("recording-{}.ron", std::process::id()));
+
+    stepper.toggle_recording(0, None);
+    stepper.frame_step();
+    for (client, movement) in [(0, Vec2::Y), (1, Vec2::X)] {
+        stepper.set_input(
+            client,
+            ScriptedInput {
+                movement,
+                look_around: Vec2::new(20.0, 0.0),
+                jump: true,
+                ..default()
+            },
+        );
+    }
+    stepper.frame_step_n(64);
+    stepper.set_input(0, ScriptedInput::default());
+    stepper.set_input(1, ScriptedInput::default());
+    stepper.frame_step_n(SETTLE_TICKS);
+    stepper.toggle_recording(0, Some(path.clone()));
+    stepper.frame_step();
+
+    let recording = Recording::load(&path).expect("the recording was not saved");
+    let _ = fs::remove_file(&path);
+    assert_eq!(recording.other_players.len(), 1);
+    assert!(
+        recording.ticks.len() > 64,
+        "only {} ticks were recorded",
+        recording.ticks.len()
+    );
+    let result = replay(&recording);
+    assert!(
+        matches!(result, Ok(None)),
+        "the replay did not match the recording: {result:?}"
+    );
+}
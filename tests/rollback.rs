@@ -0,0 +1,214 @@
+mod harness;
+
+use bevy::prelude::*;
+use harness::{assert_converged, ScriptedInput, Stepper, SETTLE_TICKS, TOLERANCE};
+use minimal_repro_lightyear_rollbacks::lightyear::my_shared::physics::MovementState;
+
+#[test]
+fn idle_player_converges() {
+    let mut stepper = Stepper::new(1);
+    stepper.connect();
+    stepper.frame_step_n(SETTLE_TICKS);
+
+    assert_converged(&mut stepper, 0);
+}
+
+#[test]
+fn moving_player_converges() {
+    let mut stepper = Stepper::new(1);
+    stepper.connect();
+
+    stepper.set_input(
+        0,
+        ScriptedInput {
+            movement: Vec2::Y,
+            ..default()
+        },
+    );
+    stepper.frame_step_n(64);
+    stepper.set_input(
+        0,
+        ScriptedInput {
+            movement: Vec2::new(1.0, 1.0).normalize(),
+            look_around: Vec2::new(5.0, 0.0),
+            ..default()
+        },
+    );
+    stepper.frame_step_n(64);
+    stepper.set_input(0, ScriptedInput::default());
+    stepper.frame_step_n(SETTLE_TICKS);
+
+    assert_converged(&mut stepper, 0);
+}
+
+#[test]
+fn jumping_player_converges() {
+    let mut stepper = Stepper::new(1);
+    stepper.connect();
+
+    for _ in 0..4 {
+        stepper.set_input(
+            0,
+            ScriptedInput {
+                movement: Vec2::Y,
+                jump: true,
+                ..default()
+            },
+        );
+        stepper.frame_step();
+        stepper.set_input(
+            0,
+            ScriptedInput {
+                movement: Vec2::Y,
+                ..default()
+            },
+        );
+        stepper.frame_step_n(48);
+    }
+    stepper.set_input(0, ScriptedInput::default());
+    stepper.frame_step_n(SETTLE_TICKS);
+
+    assert_converged(&mut stepper, 0);
+}
+
+#[test]
+fn two_players_converge() {
+    let mut stepper = Stepper::new(2);
+    stepper.connect();
+
+    stepper.set_input(
+        0,
+        ScriptedInput {
+            movement: Vec2::X,
+            ..default()
+        },
+    );
+    stepper.set_input(
+        1,
+        ScriptedInput {
+            movement: Vec2::NEG_X,
+            ..default()
+        },
+    );
+    stepper.frame_step_n(96);
+    stepper.set_input(0, ScriptedInput::default());
+    stepper.set_input(1, ScriptedInput::default());
+    stepper.frame_step_n(SETTLE_TICKS);
+
+    assert_converged(&mut stepper, 0);
+    assert_converged(&mut stepper, 1);
+}
+
+#[test]
+fn sliding_player_converges() {
+    let mut stepper = Stepper::new(1);
+    stepper.connect();
+
+    stepper.set_input(
+        0,
+        ScriptedInput {
+            movement: Vec2::Y,
+            sprint: true,
+            ..default()
+        },
+    );
+    stepper.frame_step_n(64);
+    stepper.set_input(
+        0,
+        ScriptedInput {
+            movement: Vec2::Y,
+            sprint: true,
+            crouch: true,
+            ..default()
+        },
+    );
+    stepper.frame_step_n(64);
+    stepper.set_input(0, ScriptedInput::default());
+    stepper.frame_step_n(SETTLE_TICKS);
+
+    assert_converged(&mut stepper, 0);
+    assert_eq!(stepper.server_movement_state(0), MovementState::Walking);
+}
+
+#[test]
+fn player_stays_crouched_under_the_platform() {
+    let mut stepper = Stepper::new(1);
+    stepper.connect();
+
+    // the platform is 1.5 above the ground around x = -6, too low to stand under
+    stepper.set_input(
+        0,
+        ScriptedInput {
+            movement: Vec2::NEG_X,
+            crouch: true,
+            ..default()
+        },
+    );
+    stepper.frame_step_n(96);
+    stepper.set_input(0, ScriptedInput::default());
+    stepper.frame_step_n(SETTLE_TICKS);
+
+    assert_converged(&mut stepper, 0);
+    assert_eq!(stepper.server_movement_state(0), MovementState::Crouching);
+}
+
+#[test]
+fn respawned_player_converges_at_the_spawn_point() {
+    let mut stepper = Stepper::new(1);
+    stepper.connect();
+
+    stepper.set_input(
+        0,
+        ScriptedInput {
+            movement: Vec2::X,
+            ..default()
+        },
+    );
+    stepper.frame_step_n(64);
+    stepper.set_input(0, ScriptedInput::default());
+    stepper.request_respawn(0);
+    stepper.frame_step_n(SETTLE_TICKS);
+
+    // the default map spawns everyone above the origin
+    let position = stepper.server_position(0);
+    assert!(
+        position.xz().length() <= TOLERANCE,
+        "the server has the player at {position} instead of the spawn point"
+    );
+    assert_converged(&mut stepper, 0);
+}
+
+#[test]
+fn respawned_player_snaps_instead_of_sliding() {
+    let mut stepper = Stepper::new(1);
+    stepper.connect();
+
+    stepper.set_input(
+        0,
+        ScriptedInput {
+            movement: Vec2::Y,
+            ..default()
+        },
+    );
+    stepper.frame_step_n(12);
+    stepper.set_input(0, ScriptedInput::default());
+    stepper.frame_step_n(SETTLE_TICKS);
+    let before = stepper.predicted_position(0).xz();
+    // a short walk, the respawn is no further than a misprediction could be
+    assert!(
+        before.length() > 1.0 && before.length() < 4.0,
+        "the player got to {before} instead of a step away from the spawn point"
+    );
+
+    stepper.request_respawn(0);
+    for _ in 0..SETTLE_TICKS {
+        stepper.frame_step();
+        // the body is shown either where it was or at the spawn point, never smeared in between
+        let position = stepper.predicted_position(0).xz();
+        assert!(
+            position.distance(before) <= 0.2 || position.length() <= 0.2,
+            "the player is shown at {position}, between {before} and the spawn point"
+        );
+    }
+    assert_converged(&mut stepper, 0);
+}